@@ -1,5 +1,5 @@
 use fastfile_benches::{
-    benches::{cleanup, methods::fastfile::read::read, prepare, FILE_SIZES_SMALL as FILE_SIZES},
+    benches::{methods::fastfile::read::read, prepare, FILE_SIZES_SMALL as FILE_SIZES},
     benchmark::Benchmark,
 };
 
@@ -20,5 +20,4 @@ fn main() {
         .benchmark()
         .write_results("./results/current")
         .expect("Failed to write benchmark results");
-    cleanup(params).expect("Failed to clean up test files");
 }
@@ -1,5 +1,5 @@
 use fastfile_benches::{
-    benches::{cleanup, methods::std::buf_read::read, prepare, FILE_SIZES_SMALL as FILE_SIZES},
+    benches::{methods::std::buf_read::read, prepare, FILE_SIZES_SMALL as FILE_SIZES},
     benchmark::Benchmark,
 };
 
@@ -19,5 +19,4 @@ fn main() {
         .benchmark()
         .write_results("./results/current")
         .expect("Failed to write benchmark results");
-    cleanup(params).expect("Failed to clean up test files");
 }
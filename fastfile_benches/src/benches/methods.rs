@@ -41,7 +41,6 @@ pub mod fastfile {
                         sum += buf.iter().map(|x| usize::from(*x)).sum::<usize>();
                         buf.len()
                     }
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
                     Err(e) => return Err(e),
                 };
                 reads_count += 1;
@@ -72,7 +71,7 @@ pub mod fastfile {
                     .expect("Failed to open path as FastFile")
             };
 
-            let mut buf = prepare_buf!(ffr, 8192); // This is std::io::DEFAULT_BUF_SIZE as of 21.08.2019
+            let mut buf = AlignedBuf::with_size(8192).expect("Failed to allocate buffer"); // This is std::io::DEFAULT_BUF_SIZE as of 21.08.2019
             let mut bytes_read = 0usize;
             let mut sum = 0usize;
             let mut reads_count = 0usize;
@@ -80,7 +79,6 @@ pub mod fastfile {
                 let len = match ffr.read(&mut buf[..]) {
                     Ok(0) => return Ok((bytes_read, sum, reads_count)),
                     Ok(len) => len,
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
                     Err(e) => return Err(e),
                 };
                 bytes_read += len;
@@ -1,9 +1,10 @@
-use crate::{benchmark::Param, utils::create_random_test_file};
+use crate::benchmark::Param;
 
 pub mod methods;
 
 use byte_unit::Byte;
-use std::{fs, io, path::PathBuf};
+use fastfile::testing::{self, Fixture};
+use std::io;
 
 #[rustfmt::skip]
 pub static FILE_SIZES_VERY_SMALL: &[usize] = &[
@@ -57,25 +58,18 @@ pub static FILE_SIZES_LARGE: &[usize] = &[
     1024 * 1024 * 1024,
 ];
 
-pub fn prepare(file_sizes: &[usize]) -> io::Result<Vec<Param<PathBuf>>> {
+/// Creates a test file per size; the files are deleted when the params are dropped
+pub fn prepare(file_sizes: &[usize]) -> io::Result<Vec<Param<Fixture>>> {
     let mut params = Vec::with_capacity(file_sizes.len());
 
     for &size in file_sizes {
         let name = format!("{}", size);
         let bytes = Byte::from_bytes(size as u128);
         let display_name = bytes.get_appropriate_unit(true).format(0).to_string();
-        let path = create_random_test_file(size)?;
-        let p = Param::new(name, display_name, size, path);
+        let fixture = testing::fixture(size, size as u64)?;
+        let p = Param::new(name, display_name, size, fixture);
         params.push(p);
     }
 
     Ok(params)
 }
-
-pub fn cleanup(params: Vec<Param<PathBuf>>) -> io::Result<()> {
-    for p in params {
-        fs::remove_file(p.value())?;
-    }
-
-    Ok(())
-}
@@ -0,0 +1,400 @@
+use crate::{
+    budget::{MemoryBudget, Reservation},
+    errors::*,
+    os,
+    stats::PageFaults,
+};
+
+use memmap::Mmap;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    os::unix::fs::FileExt,
+};
+
+/// Advice about the expected access pattern that a backend may pass on to the OS
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Advice {
+    /// The data is going to be read sequentially from start to end
+    Sequential,
+    /// The first `len` bytes are going to be read soon
+    WillNeed(usize),
+}
+
+/// `Backend` is the source a `FastFileReader` reads from
+///
+/// Only sequential reads and the size are mandatory. Backends that hold their data in memory
+/// should implement zero-copy reads via `is_zero_copy` and `read_slice`; `FastFileReader` then
+/// hands out slices of the backend instead of copying into its read buffer.
+pub trait Backend: Send {
+    /// Reads the next bytes into `buf` and returns how many bytes have been read; 0 signals EOF
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Returns the size of the underlying data in bytes
+    fn size(&self) -> io::Result<u64>;
+
+    /// Returns the name of this backend for the `StrategyReport`
+    fn name(&self) -> &'static str { "custom" }
+
+    /// Returns whether this backend supports `read_slice`
+    fn is_zero_copy(&self) -> bool { false }
+
+    /// Returns the next up to `max_len` bytes without copying them; an empty slice signals EOF
+    fn read_slice(&mut self, _max_len: usize) -> io::Result<&[u8]> { Err(unsupported("zero-copy reads")) }
+
+    /// Reads bytes starting at `offset` into `buf` without changing the position for `read`
+    fn read_at(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> { Err(unsupported("positional reads")) }
+
+    /// Skips the next `bytes` bytes
+    ///
+    /// The default implementation reads and discards them.
+    fn skip(&mut self, bytes: u64) -> io::Result<()> {
+        let mut scratch = [0u8; 4096];
+        let mut remaining = bytes;
+        while remaining > 0 {
+            let len = remaining.min(scratch.len() as u64) as usize;
+            match self.read(&mut scratch[..len]) {
+                Ok(0) => break,
+                Ok(n) => remaining -= n as u64,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Passes on `advice` about the expected access pattern; the default implementation ignores it
+    fn advise(&mut self, _advice: Advice) -> Result<()> { Ok(()) }
+
+    /// Returns the page faults of the pages read so far if the backend maps the file into memory
+    fn page_faults(&self) -> Option<PageFaults> { None }
+}
+
+fn unsupported(operation: &str) -> io::Error {
+    io::Error::other(format!("{} are not supported by this backend", operation))
+}
+
+/// `FileBackend` reads from a file using read system calls
+pub struct FileBackend {
+    file:   File,
+    direct: bool,
+}
+
+impl FileBackend {
+    /// Creates a new backend reading from `file`
+    pub fn new(file: File) -> FileBackend { FileBackend { file, direct: false } }
+
+    /// Creates a new backend reading from `file` without caching the data in the page cache
+    #[cfg(target_os = "macos")]
+    pub fn direct(file: File) -> Result<FileBackend> {
+        use std::os::unix::io::AsRawFd;
+
+        os::no_cache(file.as_raw_fd())?;
+        Ok(FileBackend { file, direct: true })
+    }
+}
+
+impl Backend for FileBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.file.read(buf) }
+
+    fn size(&self) -> io::Result<u64> { self.file.metadata().map(|meta| meta.len()) }
+
+    fn name(&self) -> &'static str {
+        if self.direct {
+            "direct"
+        } else {
+            "file"
+        }
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> { self.file.read_at(buf, offset) }
+
+    fn skip(&mut self, bytes: u64) -> io::Result<()> { self.file.seek(SeekFrom::Current(bytes as i64)).map(|_| ()) }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn advise(&mut self, advice: Advice) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let fd = self.file.as_raw_fd();
+        match advice {
+            Advice::Sequential => crate::os::read_ahead(fd),
+            Advice::WillNeed(len) => crate::os::read_advise(fd, len),
+        }
+    }
+}
+
+/// `MmapBackend` memory maps a file and hands out slices of the mapping
+///
+/// The mapping is accounted for in a `MemoryBudget` for as long as the backend lives.
+pub struct MmapBackend {
+    _file:        File,
+    mmap:         Mmap,
+    cursor:       SliceCursor,
+    _reservation: Reservation,
+    /// Residency of every page before the first read; empty if it could not be determined
+    resident:     Option<Vec<bool>>,
+}
+
+impl MmapBackend {
+    /// Memory maps `file`; fails if the global `MemoryBudget` does not cover the file size
+    pub fn new(file: File) -> Result<MmapBackend> { MmapBackend::within(file, MemoryBudget::global()) }
+
+    fn within(file: File, budget: &'static MemoryBudget) -> Result<MmapBackend> {
+        let mmap = unsafe { Mmap::map(&file).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))? };
+        let reservation = budget
+            .try_reserve(mmap.len())
+            .ok_or(ErrorKind::MemOpFailed("Memory budget exhausted"))?;
+        Ok(MmapBackend::with_reservation(file, mmap, reservation))
+    }
+
+    fn with_reservation(file: File, mmap: Mmap, reservation: Reservation) -> MmapBackend {
+        MmapBackend {
+            _file: file,
+            mmap,
+            cursor: SliceCursor::default(),
+            _reservation: reservation,
+            resident: None,
+        }
+    }
+}
+
+/// Returns the residency of every page of `mmap`; empty if it cannot be determined
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn resident_pages(mmap: &Mmap) -> Vec<bool> { crate::os::resident_pages(mmap.as_ptr(), mmap.len()).unwrap_or_default() }
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn resident_pages(_mmap: &Mmap) -> Vec<bool> { Vec::new() }
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn advise_mapping(mmap: &Mmap, advice: Advice) -> Result<()> {
+    crate::os::advise_mapping(mmap.as_ptr(), mmap.len(), advice)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn advise_mapping(_mmap: &Mmap, _advice: Advice) -> Result<()> { Ok(()) }
+
+impl MmapBackend {
+    // Sampling the residency of every page is proportional to the file size, so it is deferred
+    // until the file is actually read
+    fn sample_residency(&mut self) {
+        if self.resident.is_none() {
+            self.resident = Some(resident_pages(&self.mmap));
+        }
+    }
+}
+
+impl Backend for MmapBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.sample_residency();
+        Ok(self.cursor.read(&self.mmap, buf))
+    }
+
+    fn size(&self) -> io::Result<u64> { Ok(self.mmap.len() as u64) }
+
+    fn name(&self) -> &'static str { "mmap" }
+
+    fn is_zero_copy(&self) -> bool { true }
+
+    fn read_slice(&mut self, max_len: usize) -> io::Result<&[u8]> {
+        self.sample_residency();
+        Ok(self.cursor.read_slice(&self.mmap, max_len))
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(SliceCursor::read_at(&self.mmap, buf, offset))
+    }
+
+    fn skip(&mut self, bytes: u64) -> io::Result<()> {
+        self.sample_residency();
+        self.cursor.skip(&self.mmap, bytes);
+        Ok(())
+    }
+
+    fn advise(&mut self, advice: Advice) -> Result<()> { advise_mapping(&self.mmap, advice) }
+
+    fn page_faults(&self) -> Option<PageFaults> {
+        let resident = match self.resident {
+            Some(ref resident) => resident,
+            // Nothing has been read yet
+            None => return Some(PageFaults::default()),
+        };
+        if resident.is_empty() {
+            return None;
+        }
+        let pages_read = self.cursor.position().div_ceil(os::PAGE_SIZE);
+        let avoided = resident[..pages_read].iter().filter(|resident| **resident).count() as u64;
+
+        Some(PageFaults {
+            avoided,
+            incurred: pages_read as u64 - avoided,
+        })
+    }
+}
+
+/// Position of sequential reads of data in memory, shared by the backends that hand out slices
+#[derive(Default)]
+pub(crate) struct SliceCursor {
+    position: usize,
+}
+
+impl SliceCursor {
+    pub(crate) fn position(&self) -> usize { self.position }
+
+    /// Copies the next bytes of `data` into `buf` and returns their number
+    pub(crate) fn read(&mut self, data: &[u8], buf: &mut [u8]) -> usize {
+        let slice = self.read_slice(data, buf.len());
+        buf[..slice.len()].copy_from_slice(slice);
+        slice.len()
+    }
+
+    /// Returns the next at most `max_len` bytes of `data`
+    pub(crate) fn read_slice<'a>(&mut self, data: &'a [u8], max_len: usize) -> &'a [u8] {
+        let start = self.position;
+        let end = start + max_len.min(data.len() - start);
+        self.position = end;
+        &data[start..end]
+    }
+
+    /// Copies the bytes of `data` starting at `offset` into `buf` without moving the position
+    pub(crate) fn read_at(data: &[u8], buf: &mut [u8], offset: u64) -> usize {
+        let start = (offset.min(data.len() as u64)) as usize;
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        len
+    }
+
+    /// Moves the position by `bytes`, but not beyond the end of `data`
+    pub(crate) fn skip(&mut self, data: &[u8], bytes: u64) {
+        let remaining = (data.len() - self.position) as u64;
+        self.position += bytes.min(remaining) as usize;
+    }
+}
+
+/// Placeholder for a backend that has been moved out of a reader
+pub(crate) struct Detached;
+
+impl Backend for Detached {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> { Ok(0) }
+
+    fn size(&self) -> io::Result<u64> { Ok(0) }
+}
+
+/// Memory maps `file` if the global `MemoryBudget` covers the file size and falls back to
+/// streaming reads otherwise
+pub fn mmap_or_file(file: File) -> Result<Box<dyn Backend>> { mmap_or_file_within(file, MemoryBudget::global()) }
+
+pub(crate) fn mmap_or_file_within(file: File, budget: &'static MemoryBudget) -> Result<Box<dyn Backend>> {
+    let file_size = file
+        .metadata()
+        .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?
+        .len() as usize;
+    // Empty files cannot be mapped
+    let exceeds_budget = budget
+        .available()
+        .map(|available| available < file_size)
+        .unwrap_or(false);
+    if file_size == 0 || exceeds_budget {
+        return Ok(Box::new(FileBackend::new(file)));
+    }
+
+    let mmap = unsafe { Mmap::map(&file).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))? };
+    match budget.try_reserve(mmap.len()) {
+        Some(reservation) => Ok(Box::new(MmapBackend::with_reservation(file, mmap, reservation))),
+        None => Ok(Box::new(FileBackend::new(file))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+    use std::io::Write;
+
+    fn test_file(content: &[u8]) -> File {
+        let mut file = tempfile::tempfile().expect("Failed to create temp file");
+        file.write_all(content).expect("Failed to write temp file");
+        file.seek(SeekFrom::Start(0)).expect("Failed to rewind temp file");
+        file
+    }
+
+    fn backends(content: &[u8]) -> Vec<Box<dyn Backend>> {
+        vec![
+            Box::new(FileBackend::new(test_file(content))),
+            Box::new(MmapBackend::new(test_file(content)).expect("Failed to map temp file")),
+        ]
+    }
+
+    #[test]
+    fn backends_read_skip_and_read_at() {
+        let content: Vec<u8> = (0..10_000u32).map(|x| x as u8).collect();
+
+        for mut backend in backends(&content) {
+            let mut buf = [0u8; 100];
+
+            asserting("Size")
+                .that(&backend.size().expect("Failed to get size"))
+                .is_equal_to(10_000);
+            let n = backend.read(&mut buf).expect("Failed to read");
+            asserting("Read").that(&&buf[..n]).is_equal_to(&content[..n]);
+
+            backend.skip(5000).expect("Failed to skip");
+            let m = backend.read(&mut buf).expect("Failed to read");
+            asserting("Read after skip")
+                .that(&&buf[..m])
+                .is_equal_to(&content[n + 5000..n + 5000 + m]);
+
+            let k = backend.read_at(&mut buf, 9950).expect("Failed to read at");
+            asserting("Read at").that(&&buf[..k]).is_equal_to(&content[9950..]);
+        }
+    }
+
+    #[test]
+    fn mmap_backend_reads_slices() {
+        let content: Vec<u8> = (0..10_000u32).map(|x| x as u8).collect();
+        let mut backend = MmapBackend::new(test_file(&content)).expect("Failed to map temp file");
+
+        asserting("Zero-copy").that(&backend.is_zero_copy()).is_true();
+        let first = backend.read_slice(6000).expect("Failed to read slice").to_vec();
+        let second = backend.read_slice(6000).expect("Failed to read slice").to_vec();
+        let eof = backend.read_slice(6000).expect("Failed to read slice").len();
+
+        asserting("First slice").that(&&first[..]).is_equal_to(&content[..6000]);
+        asserting("Second slice")
+            .that(&&second[..])
+            .is_equal_to(&content[6000..]);
+        asserting("EOF").that(&eof).is_equal_to(0);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn mmap_backend_counts_page_faults_since_first_read() {
+        let content = vec![1u8; 4 * os::PAGE_SIZE];
+        let mut backend = MmapBackend::new(test_file(&content)).expect("Failed to map temp file");
+
+        let before = backend.page_faults().expect("No page faults for mmap backend");
+        backend.read_slice(os::PAGE_SIZE).expect("Failed to read slice");
+        backend.read_slice(2 * os::PAGE_SIZE).expect("Failed to read slice");
+        let after = backend.page_faults().expect("No page faults for mmap backend");
+
+        asserting("No pages read before")
+            .that(&before)
+            .is_equal_to(PageFaults::default());
+        // The temp file has just been written, so its pages are in the page cache
+        asserting("Pages read").that(&after).is_equal_to(PageFaults {
+            avoided:  3,
+            incurred: 0,
+        });
+    }
+
+    #[test]
+    fn file_backend_does_not_support_slices() {
+        let mut backend = FileBackend::new(test_file(b"content"));
+
+        asserting("Zero-copy").that(&backend.is_zero_copy()).is_false();
+        asserting("Read slice fails")
+            .that(&backend.read_slice(10).is_err())
+            .is_true();
+    }
+}
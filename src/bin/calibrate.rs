@@ -0,0 +1,15 @@
+use fastfile::calibration::{calibrate, CalibrationOptions};
+use std::env;
+
+fn main() {
+    let dir = env::args().nth(1).unwrap_or_else(|| ".".to_string());
+    let path = env::args()
+        .nth(2)
+        .unwrap_or_else(|| "fastfile-profile.toml".to_string());
+
+    let profile = calibrate(&dir, &CalibrationOptions::default()).expect("Failed to calibrate");
+    profile.save(&path).expect("Failed to save profile");
+
+    print!("{}", profile.to_toml().expect("Failed to serialize profile"));
+    println!("Profile for {} written to {}", dir, path);
+}
@@ -0,0 +1,190 @@
+use crate::{errors::*, os};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const UNLIMITED: usize = usize::MAX;
+
+static GLOBAL_MEMORY_BUDGET: MemoryBudget = MemoryBudget::unlimited();
+
+/// `MemoryBudget` limits the memory used by read buffers and memory mappings
+///
+/// The process-wide budget returned by `MemoryBudget::global` is unlimited unless a limit is set.
+/// Strategies and readers reserve memory from it before they allocate read buffers or map files;
+/// if the budget is exhausted, buffers shrink down to `MIN_READ_BUF_SIZE` and memory mapped
+/// backends fall back to streaming reads. Reservations are released when readers are dropped.
+pub struct MemoryBudget {
+    limit:    AtomicUsize,
+    reserved: AtomicUsize,
+}
+
+impl MemoryBudget {
+    /// Creates a new budget without a limit
+    pub const fn unlimited() -> MemoryBudget {
+        MemoryBudget {
+            limit:    AtomicUsize::new(UNLIMITED),
+            reserved: AtomicUsize::new(0),
+        }
+    }
+
+    /// Creates a new budget with a limit of `limit` bytes
+    pub fn new(limit: usize) -> MemoryBudget {
+        let budget = MemoryBudget::unlimited();
+        budget.set_limit(Some(limit));
+        budget
+    }
+
+    /// Returns the process-wide budget
+    pub fn global() -> &'static MemoryBudget { &GLOBAL_MEMORY_BUDGET }
+
+    /// Sets or removes the limit of this budget
+    ///
+    /// Lowering the limit below the currently reserved amount does not revoke reservations, but
+    /// prevents new ones until enough memory has been released.
+    pub fn set_limit(&self, limit: Option<usize>) { self.limit.store(limit.unwrap_or(UNLIMITED), Ordering::SeqCst); }
+
+    /// Returns the limit of this budget; `None` means unlimited
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::SeqCst) {
+            UNLIMITED => None,
+            limit => Some(limit),
+        }
+    }
+
+    /// Returns the number of currently reserved bytes
+    pub fn reserved(&self) -> usize { self.reserved.load(Ordering::SeqCst) }
+
+    /// Returns the number of bytes that may still be reserved; `None` means unlimited
+    pub fn available(&self) -> Option<usize> { self.limit().map(|limit| limit.saturating_sub(self.reserved())) }
+
+    /// Reserves exactly `bytes` or nothing if the budget cannot cover them
+    pub fn try_reserve(&'static self, bytes: usize) -> Option<Reservation> { self.reserve_between(bytes, bytes) }
+
+    /// Reserves as many bytes between `min` and `max` as the budget covers
+    ///
+    /// If less than `max` bytes are available, the reservation is rounded down to a multiple of
+    /// the page size. Returns `None` if not even `min` bytes are available.
+    pub fn reserve_between(&'static self, min: usize, max: usize) -> Option<Reservation> {
+        let mut reserved = self.reserved.load(Ordering::SeqCst);
+        loop {
+            let available = self.limit.load(Ordering::SeqCst).saturating_sub(reserved);
+            let bytes = if available >= max {
+                max
+            } else {
+                available / os::PAGE_SIZE * os::PAGE_SIZE
+            };
+            if bytes < min {
+                return None;
+            }
+            match self
+                .reserved
+                .compare_exchange(reserved, reserved + bytes, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return Some(Reservation { budget: self, bytes }),
+                Err(current) => reserved = current,
+            }
+        }
+    }
+
+    fn release(&self, bytes: usize) { self.reserved.fetch_sub(bytes, Ordering::SeqCst); }
+}
+
+/// `Reservation` is an amount of memory reserved from a `MemoryBudget`
+///
+/// The memory is given back to the budget when the reservation is dropped.
+pub struct Reservation {
+    budget: &'static MemoryBudget,
+    bytes:  usize,
+}
+
+impl Reservation {
+    /// Returns the number of reserved bytes
+    pub fn bytes(&self) -> usize { self.bytes }
+
+    /// Enlarges this reservation to `bytes` if the budget covers the difference
+    pub fn grow(&mut self, bytes: usize) -> Result<()> {
+        if bytes <= self.bytes {
+            return Ok(());
+        }
+        let additional = self
+            .budget
+            .try_reserve(bytes - self.bytes)
+            .ok_or(ErrorKind::MemOpFailed("Memory budget exhausted"))?;
+        self.bytes += additional.bytes;
+        std::mem::forget(additional);
+
+        Ok(())
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) { self.budget.release(self.bytes) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    fn budget(limit: usize) -> &'static MemoryBudget { Box::leak(Box::new(MemoryBudget::new(limit))) }
+
+    #[test]
+    fn global_budget_is_unlimited_by_default() { asserting("Limit").that(&MemoryBudget::global().limit()).is_none(); }
+
+    #[test]
+    fn try_reserve_and_release() {
+        let budget = budget(4 * os::PAGE_SIZE);
+
+        let reservation = budget.try_reserve(3 * os::PAGE_SIZE);
+        asserting("Reservation succeeds").that(&reservation.is_some()).is_true();
+        asserting("Reserved")
+            .that(&budget.reserved())
+            .is_equal_to(3 * os::PAGE_SIZE);
+        asserting("Available")
+            .that(&budget.available())
+            .is_equal_to(Some(os::PAGE_SIZE));
+
+        let exceeding = budget.try_reserve(2 * os::PAGE_SIZE);
+        asserting("Exceeding reservation fails")
+            .that(&exceeding.is_none())
+            .is_true();
+
+        drop(reservation);
+        asserting("Released").that(&budget.reserved()).is_equal_to(0);
+    }
+
+    #[test]
+    fn reserve_between_shrinks_to_available_pages() {
+        let budget = budget(3 * os::PAGE_SIZE + 1);
+
+        let reservation = budget
+            .reserve_between(os::PAGE_SIZE, 8 * os::PAGE_SIZE)
+            .expect("Failed to reserve");
+
+        asserting("Reserved bytes")
+            .that(&reservation.bytes())
+            .is_equal_to(3 * os::PAGE_SIZE);
+        asserting("Min not available")
+            .that(&budget.reserve_between(os::PAGE_SIZE, 8 * os::PAGE_SIZE).is_none())
+            .is_true();
+    }
+
+    #[test]
+    fn reservation_grow() {
+        let budget = budget(4 * os::PAGE_SIZE);
+        let mut reservation = budget.try_reserve(os::PAGE_SIZE).expect("Failed to reserve");
+
+        asserting("Grow within budget")
+            .that(&reservation.grow(4 * os::PAGE_SIZE).is_ok())
+            .is_true();
+        asserting("Reserved")
+            .that(&budget.reserved())
+            .is_equal_to(4 * os::PAGE_SIZE);
+        asserting("Grow beyond budget")
+            .that(&reservation.grow(5 * os::PAGE_SIZE).is_err())
+            .is_true();
+
+        drop(reservation);
+        asserting("Released").that(&budget.reserved()).is_equal_to(0);
+    }
+}
@@ -0,0 +1,601 @@
+use crate::{
+    errors::*,
+    fastfile::{optimal_buffer_size_between, FastFileReader, MIN_READ_BUF_SIZE},
+    os,
+};
+
+use std::{
+    alloc::{self, Layout},
+    any::Any,
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// `PageAlignedBuffer` is an owned, page aligned, and zero initialized memory region
+///
+/// The memory is allocated and deallocated with the same page aligned `Layout`, which makes it a
+/// sound replacement for `Vec<u8>` whenever the alignment of the allocation matters.
+pub struct PageAlignedBuffer {
+    ptr:    NonNull<u8>,
+    layout: Layout,
+}
+
+// `PageAlignedBuffer` exclusively owns its memory just like a `Vec<u8>`.
+unsafe impl Send for PageAlignedBuffer {}
+unsafe impl Sync for PageAlignedBuffer {}
+
+impl PageAlignedBuffer {
+    /// Allocates a buffer of at least `capacity` bytes
+    ///
+    /// The capacity is rounded up to a multiple of the system's page size and to at least one page.
+    pub fn new(capacity: usize) -> Result<PageAlignedBuffer> {
+        let layout = page_aligned_layout(capacity)?;
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).ok_or(ErrorKind::MemOpFailed("Memory allocation request failed"))?;
+
+        Ok(PageAlignedBuffer { ptr, layout })
+    }
+
+    /// Returns the number of bytes of this buffer
+    pub fn capacity(&self) -> usize { self.layout.size() }
+
+    /// Grows the buffer to at least `capacity` bytes while preserving its content
+    ///
+    /// The buffer stays untouched if the allocation fails.
+    pub fn grow(&mut self, capacity: usize) -> Result<()> {
+        if capacity <= self.capacity() {
+            return Ok(());
+        }
+        let mut grown = PageAlignedBuffer::new(capacity)?;
+        grown.as_mut_slice()[..self.capacity()].copy_from_slice(self.as_slice());
+        std::mem::swap(self, &mut grown);
+
+        Ok(())
+    }
+
+    /// Extracts a slice of the entire buffer
+    pub fn as_slice(&self) -> &[u8] { unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.capacity()) } }
+
+    /// Extracts a mutable slice of the entire buffer
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.capacity()) }
+    }
+}
+
+impl Drop for PageAlignedBuffer {
+    fn drop(&mut self) { unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) } }
+}
+
+fn page_aligned_layout(capacity: usize) -> Result<Layout> {
+    let size = capacity
+        .max(1)
+        .checked_add(os::PAGE_SIZE - 1)
+        .ok_or(ErrorKind::MemOpFailed("Invalid memory request"))?
+        / os::PAGE_SIZE
+        * os::PAGE_SIZE;
+    let layout = Layout::from_size_align(size, os::PAGE_SIZE)
+        .map_err(|_| Error::from(ErrorKind::MemOpFailed("Invalid memory request")))?;
+
+    Ok(layout)
+}
+
+/// `BufferPool` is a thread-safe pool of `PageAlignedBuffer`s to be reused by many readers
+///
+/// Buffers are bucketed by their capacity, i.e., the `optimal_buffer_size` of the files they have
+/// been allocated for. A `BufferPool` is a cheap handle; clones share the same pool. Returned
+/// buffers are dropped instead of pooled once the pool holds `max_pooled_bytes`.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<Mutex<BufferPoolInner>>,
+}
+
+struct BufferPoolInner {
+    max_pooled_bytes: usize,
+    pooled_bytes:     usize,
+    buckets:          HashMap<usize, Vec<PageAlignedBuffer>>,
+}
+
+impl BufferPool {
+    /// Creates a new, empty pool that holds at most `max_pooled_bytes` of idle buffers
+    pub fn new(max_pooled_bytes: usize) -> BufferPool {
+        let inner = BufferPoolInner {
+            max_pooled_bytes,
+            pooled_bytes: 0,
+            buckets: HashMap::new(),
+        };
+        BufferPool {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Takes a buffer of at least `capacity` bytes from the pool or allocates a new one
+    pub fn get(&self, capacity: usize) -> Result<PageAlignedBuffer> {
+        let layout = page_aligned_layout(capacity)?;
+        let pooled = {
+            let mut inner = self.lock();
+            let buffer = inner.buckets.get_mut(&layout.size()).and_then(|bucket| bucket.pop());
+            if let Some(ref buffer) = buffer {
+                inner.pooled_bytes -= buffer.capacity();
+            }
+            buffer
+        };
+
+        match pooled {
+            Some(buffer) => Ok(buffer),
+            None => PageAlignedBuffer::new(capacity),
+        }
+    }
+
+    /// Returns a buffer to the pool; the buffer is dropped if the pool is full
+    pub fn put(&self, buffer: PageAlignedBuffer) {
+        let mut inner = self.lock();
+        let capacity = buffer.capacity();
+        if inner.pooled_bytes + capacity > inner.max_pooled_bytes {
+            return;
+        }
+        inner.pooled_bytes += capacity;
+        inner.buckets.entry(capacity).or_default().push(buffer);
+    }
+
+    /// Returns the number of bytes currently held by idle buffers in the pool
+    pub fn pooled_bytes(&self) -> usize { self.lock().pooled_bytes }
+
+    /// Returns the maximum number of bytes the pool holds
+    pub fn max_pooled_bytes(&self) -> usize { self.lock().max_pooled_bytes }
+
+    fn lock(&self) -> MutexGuard<'_, BufferPoolInner> {
+        // The pool's state is consistent after every operation, so a poisoned lock is still usable.
+        match self.inner.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+/// `UserBuffer` is a caller owned buffer a `FastFileReader` reads into instead of its own buffer
+///
+/// It is implemented for every `AsMut<[u8]> + Send + 'static`, e.g., `AlignedBuf` or `Box<[u8]>`.
+/// See `FastFileReaderBuilder::with_buffer` for the requirements on alignment and size.
+pub trait UserBuffer: Send {
+    /// Extracts a mutable slice of the entire buffer
+    fn as_mut_slice(&mut self) -> &mut [u8];
+
+    /// Converts the boxed buffer into `Any` to recover its concrete type
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send>;
+}
+
+impl<T: AsMut<[u8]> + Send + 'static> UserBuffer for T {
+    fn as_mut_slice(&mut self) -> &mut [u8] { self.as_mut() }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any + Send> { self }
+}
+
+/// Checks that a user supplied buffer is page aligned and a non-empty multiple of the page size
+pub(crate) fn validate_user_buffer(buffer: &mut dyn UserBuffer) -> Result<()> {
+    let buf = buffer.as_mut_slice();
+    if !(buf.as_ptr() as usize).is_multiple_of(os::PAGE_SIZE) {
+        return Err(Error::from(ErrorKind::InvalidBuffer("Buffer is not page aligned")));
+    }
+    if buf.len() < MIN_READ_BUF_SIZE || !buf.len().is_multiple_of(os::PAGE_SIZE) {
+        return Err(Error::from(ErrorKind::InvalidBuffer(
+            "Buffer size is not a non-zero multiple of the page size",
+        )));
+    }
+
+    Ok(())
+}
+
+/// `AlignedBuf` is a heap allocated, page aligned, and zero initialized buffer
+///
+/// Use it as the destination buffer for `std::io::Read::read` on a `FastFileReader`. In contrast
+/// to the deprecated `prepare_buf!` macro, the memory is initialized and lives on the heap, so
+/// even buffers of `MAX_READ_BUF_SIZE` are safe to use on threads with small stacks.
+pub struct AlignedBuf {
+    inner: PageAlignedBuffer,
+    len:   usize,
+}
+
+impl AlignedBuf {
+    /// Allocates a buffer with the optimal buffer size for the specified reader
+    pub fn new(reader: &FastFileReader) -> Result<AlignedBuf> { AlignedBuf::with_size(reader.optimal_buffer_size()) }
+
+    /// Allocates a buffer of `size` bytes
+    ///
+    /// The underlying allocation is rounded up to a multiple of the system's page size, but the
+    /// buffer dereferences to exactly `size` bytes.
+    pub fn with_size(size: usize) -> Result<AlignedBuf> {
+        let inner = PageAlignedBuffer::new(size)?;
+
+        Ok(AlignedBuf { inner, len: size })
+    }
+
+    /// Returns the number of usable bytes of this buffer
+    pub fn len(&self) -> usize { self.len }
+
+    /// Returns `true` if this buffer has a length of 0
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// Extracts a slice of the entire buffer
+    pub fn as_slice(&self) -> &[u8] { &self.inner.as_slice()[..self.len] }
+
+    /// Extracts a mutable slice of the entire buffer
+    pub fn as_mut_slice(&mut self) -> &mut [u8] { &mut self.inner.as_mut_slice()[..self.len] }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] { self.as_slice() }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] { self.as_mut_slice() }
+}
+
+impl AsRef<[u8]> for AlignedBuf {
+    fn as_ref(&self) -> &[u8] { self.as_slice() }
+}
+
+impl AsMut<[u8]> for AlignedBuf {
+    fn as_mut(&mut self) -> &mut [u8] { self.as_mut_slice() }
+}
+
+/// Number of reads measured per buffer size
+const READS_PER_STEP: usize = 4;
+/// Minimal throughput gain of a buffer size to be considered better than the best one so far
+const MIN_GAIN: f64 = 1.1;
+
+/// `BufferTuner` searches the buffer size with the highest throughput by measuring reads
+///
+/// Starting from the initial size, it doubles the size as long as the throughput improves. If
+/// the first step does not improve, it halves the size instead. It settles at the best size once
+/// a step does not improve or a limit is reached; sizes stay page aligned between `min` and `max`.
+#[derive(Debug)]
+pub(crate) struct BufferTuner {
+    min:      usize,
+    max:      usize,
+    initial:  usize,
+    current:  usize,
+    best:     Option<(usize, f64)>,
+    growing:  bool,
+    settled:  bool,
+    reads:    usize,
+    bytes:    u64,
+    duration: Duration,
+}
+
+impl BufferTuner {
+    pub(crate) fn new(initial: usize, min: usize, max: usize) -> BufferTuner {
+        let initial = optimal_buffer_size_between(initial, min, max);
+        BufferTuner {
+            min,
+            max,
+            initial,
+            current: initial,
+            best: None,
+            growing: true,
+            settled: false,
+            reads: 0,
+            bytes: 0,
+            duration: Duration::default(),
+        }
+    }
+
+    /// Returns the buffer size to use for the next read
+    pub(crate) fn size(&self) -> usize { self.current }
+
+    /// Returns whether the search has finished
+    pub(crate) fn is_settled(&self) -> bool { self.settled }
+
+    /// Stops the search at `size`, e.g. because a larger buffer cannot be allocated
+    pub(crate) fn settle_at(&mut self, size: usize) {
+        self.current = size;
+        self.settled = true;
+    }
+
+    /// Records a read of `bytes` that took `duration` and returns the buffer size for the next read
+    pub(crate) fn record(&mut self, bytes: usize, duration: Duration) -> usize {
+        // An empty read signals EOF and says nothing about throughput
+        if self.settled || bytes == 0 {
+            return self.current;
+        }
+        self.reads += 1;
+        self.bytes += bytes as u64;
+        self.duration += duration;
+        if self.reads < READS_PER_STEP {
+            return self.current;
+        }
+
+        let nanos = self.duration.as_secs() as f64 * 1e9 + f64::from(self.duration.subsec_nanos());
+        let throughput = self.bytes as f64 / nanos.max(1.0);
+        self.reads = 0;
+        self.bytes = 0;
+        self.duration = Duration::default();
+
+        let improved = self.best.map(|(_, best)| throughput > best * MIN_GAIN).unwrap_or(true);
+        if improved {
+            self.best = Some((self.current, throughput));
+            if let Some(next) = self.step(self.current) {
+                self.current = next;
+                return self.current;
+            }
+        }
+
+        let best_size = self.best.map(|(size, _)| size).unwrap_or(self.initial);
+        // Growing did not help from the start, so try smaller buffers
+        if self.growing && best_size == self.initial {
+            self.growing = false;
+            if let Some(next) = self.step(best_size) {
+                self.current = next;
+                return self.current;
+            }
+        }
+        self.settle_at(best_size);
+
+        self.current
+    }
+
+    fn step(&self, from: usize) -> Option<usize> {
+        let next = if self.growing { from.saturating_mul(2) } else { from / 2 };
+        let next = optimal_buffer_size_between(next, self.min, self.max);
+        if next == from {
+            None
+        } else {
+            Some(next)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fastfile::MAX_READ_BUF_SIZE;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn page_aligned_buffer_is_page_aligned_and_zeroed() {
+        let buf = PageAlignedBuffer::new(1).expect("Failed to allocate buffer");
+
+        asserting("Capacity is rounded up to page size")
+            .that(&buf.capacity())
+            .is_equal_to(os::PAGE_SIZE);
+        asserting("Buffer is page aligned")
+            .that(&(buf.as_slice().as_ptr() as usize % os::PAGE_SIZE))
+            .is_equal_to(0);
+        asserting("Buffer is zeroed")
+            .that(&buf.as_slice().iter().all(|x| *x == 0))
+            .is_true();
+    }
+
+    #[test]
+    fn page_aligned_buffer_grow_preserves_content() {
+        let mut buf = PageAlignedBuffer::new(os::PAGE_SIZE).expect("Failed to allocate buffer");
+        for (i, x) in buf.as_mut_slice().iter_mut().enumerate() {
+            *x = i as u8;
+        }
+
+        buf.grow(2 * os::PAGE_SIZE + 1).expect("Failed to grow buffer");
+
+        asserting("Capacity after grow")
+            .that(&buf.capacity())
+            .is_equal_to(3 * os::PAGE_SIZE);
+        asserting("Buffer is page aligned")
+            .that(&(buf.as_slice().as_ptr() as usize % os::PAGE_SIZE))
+            .is_equal_to(0);
+        let (old, new) = buf.as_slice().split_at(os::PAGE_SIZE);
+        asserting("Old content is preserved")
+            .that(&old.iter().enumerate().all(|(i, x)| *x == i as u8))
+            .is_true();
+        asserting("New content is zeroed")
+            .that(&new.iter().all(|x| *x == 0))
+            .is_true();
+    }
+
+    #[test]
+    fn page_aligned_buffer_grow_to_smaller_capacity_is_noop() {
+        let mut buf = PageAlignedBuffer::new(2 * os::PAGE_SIZE).expect("Failed to allocate buffer");
+        let ptr = buf.as_slice().as_ptr();
+
+        buf.grow(os::PAGE_SIZE).expect("Failed to grow buffer");
+
+        asserting("Capacity")
+            .that(&buf.capacity())
+            .is_equal_to(2 * os::PAGE_SIZE);
+        asserting("Allocation is unchanged")
+            .that(&buf.as_slice().as_ptr())
+            .is_equal_to(ptr);
+    }
+
+    #[test]
+    fn page_aligned_buffer_invalid_capacity() {
+        let res = PageAlignedBuffer::new(usize::max_value());
+
+        asserting("Allocation fails").that(&res.is_err()).is_true();
+        let err = res.err().unwrap(); // Safe, bc we checked above
+        asserting("Error kind")
+            .that(err.kind())
+            .is_equal_to(&ErrorKind::MemOpFailed("Invalid memory request"));
+    }
+
+    #[test]
+    fn buffer_pool_reuses_buffers() {
+        let pool = BufferPool::new(16 * os::PAGE_SIZE);
+        let buf = pool.get(os::PAGE_SIZE).expect("Failed to get buffer");
+        let ptr = buf.as_slice().as_ptr();
+
+        pool.put(buf);
+        asserting("Pooled bytes after put")
+            .that(&pool.pooled_bytes())
+            .is_equal_to(os::PAGE_SIZE);
+
+        let buf = pool.get(os::PAGE_SIZE - 1).expect("Failed to get buffer");
+        asserting("Buffer is reused")
+            .that(&buf.as_slice().as_ptr())
+            .is_equal_to(ptr);
+        asserting("Pooled bytes after get")
+            .that(&pool.pooled_bytes())
+            .is_equal_to(0);
+    }
+
+    #[test]
+    fn buffer_pool_is_size_classed() {
+        let pool = BufferPool::new(16 * os::PAGE_SIZE);
+        let buf = pool.get(os::PAGE_SIZE).expect("Failed to get buffer");
+        pool.put(buf);
+
+        let buf = pool.get(2 * os::PAGE_SIZE).expect("Failed to get buffer");
+
+        asserting("Capacity")
+            .that(&buf.capacity())
+            .is_equal_to(2 * os::PAGE_SIZE);
+        asserting("Smaller buffer stays pooled")
+            .that(&pool.pooled_bytes())
+            .is_equal_to(os::PAGE_SIZE);
+    }
+
+    #[test]
+    fn buffer_pool_respects_max_pooled_bytes() {
+        let pool = BufferPool::new(2 * os::PAGE_SIZE);
+        let bufs: Vec<_> = (0..3)
+            .map(|_| pool.get(os::PAGE_SIZE).expect("Failed to get buffer"))
+            .collect();
+
+        for buf in bufs {
+            pool.put(buf);
+        }
+
+        asserting("Pooled bytes are capped")
+            .that(&pool.pooled_bytes())
+            .is_equal_to(2 * os::PAGE_SIZE);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn buffer_pool_gets_buffer_back_from_reader() {
+        use crate::fastfile::{FastFile, FastFileRead};
+
+        let pool = BufferPool::new(MAX_READ_BUF_SIZE);
+        {
+            let mut ffr = FastFile::read("Cargo.toml")
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer_pool(&pool)
+                .open()
+                .expect("Failed to open path as FastFile");
+            let buf = ffr.read().expect("Failed to fastread file");
+            asserting("Read bytes").that(&buf.is_empty()).is_false();
+            asserting("Buffer is in use").that(&pool.pooled_bytes()).is_equal_to(0);
+        }
+
+        asserting("Buffer returned on drop")
+            .that(&pool.pooled_bytes())
+            .is_equal_to(os::PAGE_SIZE);
+    }
+
+    #[test]
+    fn with_size_is_page_aligned_and_zeroed() {
+        let buf = AlignedBuf::with_size(3 * os::PAGE_SIZE + 1).expect("Failed to allocate buffer");
+
+        asserting("Buffer length")
+            .that(&buf.len())
+            .is_equal_to(3 * os::PAGE_SIZE + 1);
+        asserting("Buffer is page aligned")
+            .that(&(buf.as_ptr() as usize % os::PAGE_SIZE))
+            .is_equal_to(0);
+        asserting("Buffer is zeroed")
+            .that(&buf.iter().all(|x| *x == 0))
+            .is_true();
+    }
+
+    #[test]
+    fn with_size_zero() {
+        let mut buf = AlignedBuf::with_size(0).expect("Failed to allocate buffer");
+
+        asserting("Buffer is empty").that(&buf.is_empty()).is_true();
+        asserting("Mutable slice is empty")
+            .that(&buf.as_mut_slice().len())
+            .is_equal_to(0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn with_size_max_read_buf_size_is_writable() {
+        let mut buf = AlignedBuf::with_size(MAX_READ_BUF_SIZE).expect("Failed to allocate buffer");
+        for x in buf.iter_mut() {
+            *x = 0xff;
+        }
+
+        asserting("Buffer is written")
+            .that(&buf.iter().all(|x| *x == 0xff))
+            .is_true();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn with_size_max_read_buf_size_on_small_stack() {
+        let handle = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| AlignedBuf::with_size(MAX_READ_BUF_SIZE).map(|buf| buf.len()))
+            .expect("Failed to spawn thread");
+        let len = handle
+            .join()
+            .expect("Thread panicked")
+            .expect("Failed to allocate buffer");
+
+        asserting("Buffer length").that(&len).is_equal_to(MAX_READ_BUF_SIZE);
+    }
+
+    fn tune(tuner: &mut BufferTuner, throughput: impl Fn(usize) -> u64) {
+        for _ in 0..100 {
+            let size = tuner.size();
+            // `throughput` bytes per microsecond
+            tuner.record(size, Duration::from_nanos(1000 * size as u64 / throughput(size)));
+        }
+    }
+
+    #[test]
+    fn buffer_tuner_grows_while_throughput_improves() {
+        let mut tuner = BufferTuner::new(os::PAGE_SIZE, os::PAGE_SIZE, 64 * os::PAGE_SIZE);
+
+        // Throughput improves up to 16 pages
+        tune(&mut tuner, |size| (size.min(16 * os::PAGE_SIZE) / os::PAGE_SIZE) as u64);
+
+        asserting("Tuner is settled").that(&tuner.is_settled()).is_true();
+        asserting("Buffer size")
+            .that(&tuner.size())
+            .is_equal_to(16 * os::PAGE_SIZE);
+    }
+
+    #[test]
+    fn buffer_tuner_shrinks_if_growing_does_not_help() {
+        let mut tuner = BufferTuner::new(16 * os::PAGE_SIZE, os::PAGE_SIZE, 64 * os::PAGE_SIZE);
+
+        // Throughput is best up to 8 pages, so the largest of these sizes wins
+        tune(&mut tuner, |size| if size <= 8 * os::PAGE_SIZE { 100 } else { 10 });
+
+        asserting("Tuner is settled").that(&tuner.is_settled()).is_true();
+        asserting("Buffer size")
+            .that(&tuner.size())
+            .is_equal_to(8 * os::PAGE_SIZE);
+    }
+
+    #[test]
+    fn buffer_tuner_stays_within_limits() {
+        let mut tuner = BufferTuner::new(0, 2 * os::PAGE_SIZE, 8 * os::PAGE_SIZE);
+        asserting("Initial size is limited")
+            .that(&tuner.size())
+            .is_equal_to(2 * os::PAGE_SIZE);
+
+        // Throughput always improves with size
+        tune(&mut tuner, |size| size as u64);
+
+        asserting("Tuner is settled").that(&tuner.is_settled()).is_true();
+        asserting("Buffer size")
+            .that(&tuner.size())
+            .is_equal_to(8 * os::PAGE_SIZE);
+    }
+}
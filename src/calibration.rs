@@ -0,0 +1,241 @@
+use crate::{
+    errors::*,
+    fastfile::{optimal_buffer_size, FastFile, MAX_READ_BUF_SIZE, MIN_READ_BUF_SIZE},
+    fixture_data::FixtureData,
+    strategy::{Profile, ProfileBackend, ProfiledReaderStrategy, SizeClass},
+    FastFileRead,
+};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use crate::os;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::os::unix::io::AsRawFd;
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// `CalibrationOptions` selects what `calibrate` measures
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CalibrationOptions {
+    /// Sizes of the test files in bytes; each size becomes the upper limit of a size class
+    pub file_sizes:   Vec<usize>,
+    /// Read buffer sizes to measure for the file backend; must be multiples of the page size
+    pub buffer_sizes: Vec<usize>,
+    /// Number of reads per candidate; the first read is cold, and the fastest of the others is
+    /// added to it
+    pub iterations:   usize,
+}
+
+impl Default for CalibrationOptions {
+    fn default() -> CalibrationOptions {
+        CalibrationOptions {
+            file_sizes:   vec![4 * 1024, 64 * 1024, 1024 * 1024, 16 * 1024 * 1024, 64 * 1024 * 1024],
+            buffer_sizes: vec![
+                MIN_READ_BUF_SIZE,
+                16 * MIN_READ_BUF_SIZE,
+                64 * MIN_READ_BUF_SIZE,
+                MAX_READ_BUF_SIZE,
+            ],
+            iterations:   5,
+        }
+    }
+}
+
+/// Measures the file and mmap backends and buffer sizes for files in `dir` and returns the
+/// profile of the fastest setup per size class
+///
+/// Test files are created in `dir` and removed afterwards, so `dir` should be on the file system
+/// the profile is meant for. Every candidate reads a fresh test file that has been kept out of the
+/// page cache, so its first read comes from the device; the fastest of the repeated reads adds the
+/// cost of reading from the page cache. On platforms other than macOS and Linux, the test files
+/// stay in the page cache, so the first read is warm as well.
+pub fn calibrate<P: AsRef<Path>>(dir: P, options: &CalibrationOptions) -> Result<Profile> {
+    let mut file_sizes = options.file_sizes.clone();
+    file_sizes.sort();
+    file_sizes.dedup();
+
+    let mut size_classes: Vec<SizeClass> = Vec::new();
+    for (i, &size) in file_sizes.iter().enumerate() {
+        let max_size = if i + 1 < file_sizes.len() { Some(size) } else { None };
+
+        let mut best: Option<(Duration, SizeClass)> = None;
+        for candidate in candidates(size, &options.buffer_sizes, max_size) {
+            let test_file = TestFile::create(dir.as_ref(), size)?;
+            let duration = measure(&test_file.path, &candidate, options.iterations)?;
+            if best.as_ref().map(|(fastest, _)| duration < *fastest).unwrap_or(true) {
+                best = Some((duration, candidate));
+            }
+        }
+
+        if let Some((_, class)) = best {
+            match size_classes.last_mut() {
+                // Merge with the previous class if the same setup won
+                Some(ref mut last) if last.backend == class.backend && last.buffer_size == class.buffer_size => {
+                    last.max_size = class.max_size
+                }
+                _ => size_classes.push(class),
+            }
+        }
+    }
+
+    let profile = Profile { size_classes };
+    profile.validate()?;
+
+    Ok(profile)
+}
+
+fn candidates(size: usize, buffer_sizes: &[usize], max_size: Option<usize>) -> Vec<SizeClass> {
+    let mut candidates = vec![SizeClass {
+        max_size,
+        backend: ProfileBackend::Mmap,
+        buffer_size: optimal_buffer_size(size),
+    }];
+    // Buffers larger than the file cannot make a difference
+    let mut useful: Vec<usize> = buffer_sizes
+        .iter()
+        .cloned()
+        .filter(|buffer_size| *buffer_size <= optimal_buffer_size(size))
+        .collect();
+    if useful.is_empty() {
+        useful.extend(buffer_sizes.iter().min().cloned());
+    }
+    candidates.extend(useful.into_iter().map(|buffer_size| {
+        SizeClass {
+            max_size,
+            backend: ProfileBackend::File,
+            buffer_size,
+        }
+    }));
+
+    candidates
+}
+
+fn measure(path: &Path, candidate: &SizeClass, iterations: usize) -> Result<Duration> {
+    let strategy = ProfiledReaderStrategy::new(Profile {
+        size_classes: vec![SizeClass {
+            max_size: None,
+            ..candidate.clone()
+        }],
+    })?;
+    let read = || -> Result<Duration> {
+        let start = Instant::now();
+        let mut ffr = FastFile::read(path)?.open_with_strategy(&strategy)?;
+        while !ffr.read()?.is_empty() {}
+        Ok(start.elapsed())
+    };
+
+    let cold = read()?;
+    let mut fastest = None;
+    for _ in 1..iterations {
+        let duration = read()?;
+        fastest = Some(
+            fastest
+                .map(|fastest: Duration| fastest.min(duration))
+                .unwrap_or(duration),
+        );
+    }
+
+    Ok(cold + fastest.unwrap_or_default())
+}
+
+/// Pseudo random test file that is removed on drop
+struct TestFile {
+    path: PathBuf,
+}
+
+impl TestFile {
+    /// Writes `size` bytes to a new file in `dir` without keeping them in the page cache
+    fn create(dir: &Path, size: usize) -> Result<TestFile> {
+        let path = dir.join(format!(".fastfile-calibration-{}", size));
+        let test_file = TestFile { path };
+        let at_path = |e| Error::with_source(ErrorKind::FileOpFailed, e).with_path(&test_file.path);
+        let file = File::create(&test_file.path).map_err(at_path)?;
+        bypass_page_cache(&file).map_err(|e| e.with_path(&test_file.path))?;
+
+        let mut writer = BufWriter::new(&file);
+        let mut data = FixtureData::new(size as u64);
+        let mut buf = [0u8; 8 * 1024];
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            data.fill(&mut buf[..len]);
+            writer.write_all(&buf[..len]).map_err(at_path)?;
+            remaining -= len;
+        }
+        writer.flush().map_err(at_path)?;
+        drop(writer);
+        file.sync_all().map_err(at_path)?;
+        evict_from_page_cache(&file).map_err(|e| e.with_path(&test_file.path))?;
+
+        Ok(test_file)
+    }
+}
+
+impl Drop for TestFile {
+    fn drop(&mut self) { let _ = fs::remove_file(&self.path); }
+}
+
+#[cfg(target_os = "macos")]
+fn bypass_page_cache(file: &File) -> Result<()> { os::no_cache(file.as_raw_fd()) }
+
+#[cfg(not(target_os = "macos"))]
+fn bypass_page_cache(_file: &File) -> Result<()> { Ok(()) }
+
+/// Drops the written test file from the page cache where writes cannot bypass it
+#[cfg(target_os = "linux")]
+fn evict_from_page_cache(file: &File) -> Result<()> { os::drop_page_cache(file.as_raw_fd()) }
+
+#[cfg(not(target_os = "linux"))]
+fn evict_from_page_cache(_file: &File) -> Result<()> { Ok(()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::os::PAGE_SIZE;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn calibrate_creates_valid_profile() {
+        let dir = tempfile::tempdir().expect("Failed to create test directory");
+        let options = CalibrationOptions {
+            file_sizes:   vec![100_000, 4096],
+            buffer_sizes: vec![PAGE_SIZE, 4 * PAGE_SIZE],
+            iterations:   1,
+        };
+
+        let profile = calibrate(dir.path(), &options).expect("Failed to calibrate");
+
+        asserting("Profile is valid")
+            .that(&profile.validate().is_ok())
+            .is_true();
+        asserting("Last class is unlimited")
+            .that(&profile.size_classes.last().and_then(|class| class.max_size))
+            .is_none();
+        asserting("Test files are removed")
+            .that(&fs::read_dir(dir.path()).unwrap().count())
+            .is_equal_to(0);
+    }
+
+    #[test]
+    fn candidates_skip_buffers_larger_than_file() {
+        let candidates = candidates(PAGE_SIZE, &[PAGE_SIZE, 4 * PAGE_SIZE], None);
+
+        asserting("Candidates")
+            .that(
+                &candidates
+                    .iter()
+                    .map(|c| (c.backend, c.buffer_size))
+                    .collect::<Vec<_>>(),
+            )
+            .is_equal_to(vec![
+                (ProfileBackend::Mmap, PAGE_SIZE),
+                (ProfileBackend::File, PAGE_SIZE),
+            ]);
+    }
+}
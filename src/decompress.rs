@@ -0,0 +1,303 @@
+use crate::{backend::Backend, errors::*};
+
+use flate2::read::MultiGzDecoder;
+use std::io::{self, Read};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Selects whether and how a `FastFileReader` decompresses the file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decompress {
+    /// Detect the compression format by its magic bytes; uncompressed files are read as they are
+    Auto,
+    /// Decompress gzip
+    Gzip,
+    /// Decompress zstd; requires the `zstd` feature
+    Zstd,
+    /// Read the file as it is
+    None,
+}
+
+/// Compression format of a file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Compression {
+    /// gzip, RFC 1952
+    Gzip,
+    /// Zstandard, RFC 8878
+    Zstd,
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+}
+
+/// `DecompressingBackend` decodes the data of another backend
+///
+/// `size` returns the size of the compressed data.
+struct DecompressingBackend {
+    decoder:         Box<dyn Read + Send>,
+    compressed_size: u64,
+}
+
+impl Backend for DecompressingBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.decoder.read(buf) }
+
+    fn size(&self) -> io::Result<u64> { Ok(self.compressed_size) }
+}
+
+/// Result of wrapping a backend according to a `Decompress` selection
+pub(crate) struct Decompression {
+    pub(crate) backend:                Box<dyn Backend>,
+    pub(crate) compression:            Option<Compression>,
+    pub(crate) uncompressed_size_hint: Option<u64>,
+}
+
+/// Wraps `inner` in a `DecompressingBackend` if `decompress` selects or detects a compression
+/// format
+pub(crate) fn decompress(mut inner: Box<dyn Backend>, decompress: Decompress) -> Result<Decompression> {
+    if decompress == Decompress::None {
+        return Ok(Decompression {
+            backend:                inner,
+            compression:            None,
+            uncompressed_size_hint: None,
+        });
+    }
+
+    // Prefer positional reads to detect the format, so zero-copy backends stay untouched for
+    // uncompressed files; otherwise the consumed magic bytes are replayed to the decoder
+    let mut magic = [0u8; 4];
+    let (magic_len, consumed) = match inner.read_at(&mut magic, 0) {
+        Ok(len) => (len, 0),
+        Err(_) => {
+            let len = read_fully(inner.as_mut(), &mut magic)?;
+            (len, len)
+        }
+    };
+    let magic = &magic[..magic_len];
+
+    let compression = match decompress {
+        Decompress::Auto => Compression::detect(magic),
+        Decompress::Gzip => Some(Compression::Gzip),
+        Decompress::Zstd => Some(Compression::Zstd),
+        Decompress::None => None,
+    };
+    if compression.is_none() && consumed == 0 {
+        return Ok(Decompression {
+            backend: inner,
+            compression,
+            uncompressed_size_hint: None,
+        });
+    }
+
+    let compressed_size = inner.size()?;
+    let uncompressed_size_hint = match compression {
+        Some(Compression::Gzip) => gzip_size_hint(inner.as_ref(), compressed_size),
+        _ => None,
+    };
+
+    let prefix = io::Cursor::new(magic[..consumed].to_vec());
+    let source = prefix.chain(BackendRead(inner));
+    let decoder: Box<dyn Read + Send> = match compression {
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(source)),
+        Some(Compression::Zstd) => zstd_decoder(source)?,
+        None => Box::new(source),
+    };
+
+    Ok(Decompression {
+        backend: Box::new(DecompressingBackend {
+            decoder,
+            compressed_size,
+        }),
+        compression,
+        uncompressed_size_hint,
+    })
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decoder<R: Read + Send + 'static>(source: R) -> Result<Box<dyn Read + Send>> {
+    let decoder =
+        zstd::stream::read::Decoder::new(source).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?;
+    Ok(Box::new(decoder))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decoder<R: Read + Send + 'static>(_source: R) -> Result<Box<dyn Read + Send>> {
+    Err(ErrorKind::Unsupported("zstd decompression requires the `zstd` feature").into())
+}
+
+/// Reads the uncompressed size from the trailer of the last gzip member
+///
+/// The trailer stores the size modulo 2^32 and does not cover preceding members, so the hint is
+/// only exact for single member files of less than 4 GiB.
+fn gzip_size_hint(inner: &dyn Backend, compressed_size: u64) -> Option<u64> {
+    if compressed_size < 18 {
+        return None;
+    }
+    let mut trailer = [0u8; 4];
+    match inner.read_at(&mut trailer, compressed_size - 4) {
+        Ok(4) => Some(u64::from(u32::from_le_bytes(trailer))),
+        _ => None,
+    }
+}
+
+fn read_fully(inner: &mut dyn Backend, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match inner.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::with_source(ErrorKind::FileOpFailed, e)),
+        }
+    }
+
+    Ok(len)
+}
+
+struct BackendRead(Box<dyn Backend>);
+
+impl Read for BackendRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.0.read(buf) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing::{self, FaultyBackend, MemoryBackend};
+
+    use flate2::{write::GzEncoder, Compression as GzLevel};
+    use spectral::prelude::*;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), GzLevel::default());
+        encoder.write_all(data).expect("Failed to compress");
+        encoder.finish().expect("Failed to compress")
+    }
+
+    fn read_all(backend: &mut dyn Backend) -> Vec<u8> {
+        let mut content = Vec::new();
+        let mut buf = [0u8; 1000];
+        loop {
+            match backend.read(&mut buf).expect("Failed to read") {
+                0 => return content,
+                n => content.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn auto_detects_gzip() {
+        let expected = testing::fixture_bytes(100_000, 1);
+        let inner = Box::new(MemoryBackend::new(gzip(&expected)));
+
+        let mut decompression = decompress(inner, Decompress::Auto).expect("Failed to decompress");
+
+        asserting("Compression")
+            .that(&decompression.compression)
+            .is_equal_to(Some(Compression::Gzip));
+        asserting("Uncompressed size hint")
+            .that(&decompression.uncompressed_size_hint)
+            .is_equal_to(Some(100_000));
+        asserting("Content")
+            .that(&read_all(decompression.backend.as_mut()))
+            .is_equal_to(&expected);
+    }
+
+    #[test]
+    fn auto_detects_gzip_without_positional_reads() {
+        let expected = testing::fixture_bytes(100_000, 2);
+        let mut compressed = gzip(&expected);
+        compressed.extend(gzip(&expected));
+        let inner = Box::new(FaultyBackend::new(MemoryBackend::new(compressed)).short_reads(1));
+
+        let mut decompression = decompress(inner, Decompress::Auto).expect("Failed to decompress");
+
+        asserting("Compression")
+            .that(&decompression.compression)
+            .is_equal_to(Some(Compression::Gzip));
+        asserting("Uncompressed size hint")
+            .that(&decompression.uncompressed_size_hint)
+            .is_none();
+        asserting("Content of all members")
+            .that(&read_all(decompression.backend.as_mut()))
+            .is_equal_to([&expected[..], &expected[..]].concat());
+    }
+
+    #[test]
+    fn gzip_size_hint_covers_last_member_only() {
+        let first = testing::fixture_bytes(100_000, 6);
+        let last = testing::fixture_bytes(1000, 7);
+        let mut compressed = gzip(&first);
+        compressed.extend(gzip(&last));
+        let inner = Box::new(MemoryBackend::new(compressed));
+
+        let mut decompression = decompress(inner, Decompress::Auto).expect("Failed to decompress");
+
+        asserting("Uncompressed size hint")
+            .that(&decompression.uncompressed_size_hint)
+            .is_equal_to(Some(1000));
+        asserting("Content of all members")
+            .that(&read_all(decompression.backend.as_mut()))
+            .is_equal_to([&first[..], &last[..]].concat());
+    }
+
+    #[test]
+    fn auto_keeps_uncompressed_backend() {
+        let expected = testing::fixture_bytes(100_000, 3);
+        let inner = Box::new(MemoryBackend::new(expected.clone()));
+
+        let mut decompression = decompress(inner, Decompress::Auto).expect("Failed to decompress");
+
+        asserting("Compression").that(&decompression.compression).is_none();
+        asserting("Zero-copy")
+            .that(&decompression.backend.is_zero_copy())
+            .is_true();
+        asserting("Content")
+            .that(&read_all(decompression.backend.as_mut()))
+            .is_equal_to(&expected);
+    }
+
+    #[test]
+    fn auto_replays_magic_of_uncompressed_backend_without_positional_reads() {
+        let expected = testing::fixture_bytes(100_000, 4);
+        let inner = Box::new(FaultyBackend::new(MemoryBackend::new(expected.clone())));
+
+        let mut decompression = decompress(inner, Decompress::Auto).expect("Failed to decompress");
+
+        asserting("Compression").that(&decompression.compression).is_none();
+        asserting("Content")
+            .that(&read_all(decompression.backend.as_mut()))
+            .is_equal_to(&expected);
+    }
+
+    #[test]
+    fn corrupt_gzip_fails() {
+        let mut compressed = gzip(&testing::fixture_bytes(100_000, 5));
+        let len = compressed.len();
+        compressed[len / 2] ^= 0xff;
+        let inner = Box::new(MemoryBackend::new(compressed));
+
+        let mut decompression = decompress(inner, Decompress::Gzip).expect("Failed to decompress");
+        let mut buf = [0u8; 1000];
+        let res = loop {
+            match decompression.backend.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(e) => break Err(e),
+            }
+        };
+
+        asserting("Read fails").that(&res.is_err()).is_true();
+    }
+}
@@ -0,0 +1,388 @@
+use crate::{errors::*, fastfile::FastFile, strategy::StrategyConfig, FastFileRead};
+
+#[cfg(feature = "digest")]
+use sha2::Digest as _;
+#[cfg(feature = "digest")]
+use std::hash::Hasher as _;
+use std::{fmt, io, path::Path, result};
+#[cfg(feature = "digest")]
+use twox_hash::XxHash64;
+
+/// Files of at least this size are memory mapped and hashed in parallel by `digest_file` if the
+/// algorithm supports it
+pub const PARALLEL_THRESHOLD: usize = 1024 * 1024;
+
+/// Checksum and hash algorithms supported for inline digests
+///
+/// Computing digests requires the `digest` feature; `Hasher::new` fails with
+/// `ErrorKind::Unsupported` for algorithms whose feature is not enabled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Algorithm {
+    /// CRC-32C (Castagnoli)
+    Crc32c,
+    /// xxHash64 with seed 0
+    XxHash64,
+    /// SHA-256
+    Sha256,
+    /// BLAKE3; requires the `blake3` feature instead of the `digest` feature
+    Blake3,
+}
+
+impl Algorithm {
+    /// Returns whether a single input can be hashed on multiple threads
+    pub fn is_parallel(self) -> bool { self == Algorithm::Blake3 }
+}
+
+/// `Digest` is the checksum or hash of some data
+///
+/// The bytes are in the canonical, big-endian representation of the algorithm, so the hex
+/// representation matches common command line tools.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Digest {
+    algorithm: Algorithm,
+    bytes:     Vec<u8>,
+}
+
+impl Digest {
+    #[cfg(any(feature = "digest", feature = "blake3"))]
+    fn new(algorithm: Algorithm, bytes: &[u8]) -> Digest {
+        Digest {
+            algorithm,
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    /// Creates a digest of `algorithm` from its hex representation; `None` if `hex` is invalid
+    pub fn from_hex(algorithm: Algorithm, hex: &str) -> Option<Digest> {
+        let len = match algorithm {
+            Algorithm::Crc32c => 4,
+            Algorithm::XxHash64 => 8,
+            Algorithm::Sha256 => 32,
+            Algorithm::Blake3 => 32,
+        };
+        if hex.len() != 2 * len || !hex.is_ascii() {
+            return None;
+        }
+        let bytes = (0..len)
+            .map(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        Some(Digest { algorithm, bytes })
+    }
+
+    /// Returns the algorithm of this digest
+    pub fn algorithm(&self) -> Algorithm { self.algorithm }
+
+    /// Returns the hex representation of this digest
+    pub fn to_hex(&self) -> String { self.bytes.iter().map(|b| format!("{:02x}", b)).collect() }
+}
+
+impl AsRef<[u8]> for Digest {
+    fn as_ref(&self) -> &[u8] { &self.bytes }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { write!(f, "{:?}:{}", self.algorithm, self.to_hex()) }
+}
+
+/// `Hasher` computes a `Digest` incrementally
+#[derive(Clone)]
+pub struct Hasher {
+    state: State,
+}
+
+#[derive(Clone)]
+enum State {
+    #[cfg(feature = "digest")]
+    Crc32c(u32),
+    #[cfg(feature = "digest")]
+    XxHash64(XxHash64),
+    #[cfg(feature = "digest")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3_hasher::Hasher>),
+}
+
+impl Hasher {
+    /// Creates a new hasher for `algorithm`
+    ///
+    /// Fails with `ErrorKind::Unsupported` if the feature `algorithm` requires is not enabled.
+    pub fn new(algorithm: Algorithm) -> Result<Hasher> {
+        let state: result::Result<State, ErrorKind> = match algorithm {
+            #[cfg(feature = "digest")]
+            Algorithm::Crc32c => Ok(State::Crc32c(0)),
+            #[cfg(feature = "digest")]
+            Algorithm::XxHash64 => Ok(State::XxHash64(XxHash64::with_seed(0))),
+            #[cfg(feature = "digest")]
+            Algorithm::Sha256 => Ok(State::Sha256(sha2::Sha256::new())),
+            #[cfg(not(feature = "digest"))]
+            Algorithm::Crc32c | Algorithm::XxHash64 | Algorithm::Sha256 => {
+                Err(ErrorKind::Unsupported("digests require the `digest` feature"))
+            }
+            #[cfg(feature = "blake3")]
+            Algorithm::Blake3 => Ok(State::Blake3(Box::new(blake3_hasher::Hasher::new()))),
+            #[cfg(not(feature = "blake3"))]
+            Algorithm::Blake3 => Err(ErrorKind::Unsupported("BLAKE3 requires the `blake3` feature")),
+        };
+        state.map(|state| Hasher { state }).map_err(Error::from)
+    }
+
+    /// Feeds `data` into the digest
+    #[cfg_attr(not(any(feature = "digest", feature = "blake3")), allow(unused_variables))]
+    pub fn update(&mut self, data: &[u8]) {
+        match self.state {
+            #[cfg(feature = "digest")]
+            State::Crc32c(ref mut crc) => *crc = crc32c::crc32c_append(*crc, data),
+            #[cfg(feature = "digest")]
+            State::XxHash64(ref mut hasher) => hasher.write(data),
+            #[cfg(feature = "digest")]
+            State::Sha256(ref mut hasher) => hasher.input(data),
+            #[cfg(feature = "blake3")]
+            State::Blake3(ref mut hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Feeds `data` into the digest using all cores if the algorithm supports it
+    pub fn update_parallel(&mut self, data: &[u8]) {
+        match self.state {
+            #[cfg(feature = "blake3")]
+            State::Blake3(ref mut hasher) => {
+                hasher.update_with_join::<blake3_hasher::join::RayonJoin>(data);
+            }
+            #[allow(unreachable_patterns)]
+            _ => self.update(data),
+        }
+    }
+
+    /// Returns the digest of all data fed so far
+    pub fn finish(self) -> Digest {
+        match self.state {
+            #[cfg(feature = "digest")]
+            State::Crc32c(crc) => Digest::new(Algorithm::Crc32c, &crc.to_be_bytes()),
+            #[cfg(feature = "digest")]
+            State::XxHash64(hasher) => Digest::new(Algorithm::XxHash64, &hasher.finish().to_be_bytes()),
+            #[cfg(feature = "digest")]
+            State::Sha256(hasher) => Digest::new(Algorithm::Sha256, &hasher.result()),
+            #[cfg(feature = "blake3")]
+            State::Blake3(hasher) => Digest::new(Algorithm::Blake3, hasher.finalize().as_bytes()),
+        }
+    }
+}
+
+/// Computes the digest of the file at `path` with `algorithm`
+///
+/// The file is read with the default reader strategy. For algorithms that hash in parallel, the
+/// strategy memory maps files of at least `PARALLEL_THRESHOLD` bytes unless the file system or the
+/// `MemoryBudget` rule it out; mapped files are hashed on all cores. All other files are hashed in
+/// a single pass.
+pub fn digest_file<P: AsRef<Path>>(path: P, algorithm: Algorithm) -> Result<Digest> {
+    let mut hasher = Hasher::new(algorithm)?;
+    let mut config = StrategyConfig::default();
+    if algorithm.is_parallel() {
+        config.mmap_threshold = Some(PARALLEL_THRESHOLD);
+    }
+    let mut ffr = FastFile::read(path)?.open_with_config(config)?;
+
+    if ffr.is_zero_copy() {
+        hasher.update_parallel(FastFileRead::read_to_end(&mut ffr)?);
+    } else {
+        loop {
+            let buf = FastFileRead::read(&mut ffr)?;
+            if buf.is_empty() {
+                break;
+            }
+            hasher.update(buf);
+        }
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Computes the digest of the data delivered by a reader and verifies it at EOF
+#[derive(Default)]
+pub(crate) struct Checksum {
+    hasher:   Option<Hasher>,
+    digest:   Option<Digest>,
+    expected: Option<Digest>,
+}
+
+impl Checksum {
+    /// Fails if the algorithm of `expected` or `algorithm` is not supported by this build
+    pub(crate) fn new(algorithm: Option<Algorithm>, expected: Option<Digest>) -> Result<Checksum> {
+        let algorithm = expected.as_ref().map(|digest| digest.algorithm).or(algorithm);
+        let hasher = match algorithm {
+            Some(algorithm) => Some(Hasher::new(algorithm)?),
+            None => None,
+        };
+
+        Ok(Checksum {
+            hasher,
+            digest: None,
+            expected,
+        })
+    }
+
+    pub(crate) fn digest(&self) -> Option<&Digest> { self.digest.as_ref() }
+
+    /// Feeds a delivered `chunk`; an empty chunk signals EOF and finishes the digest
+    ///
+    /// At EOF, a digest that differs from the expected one is returned as error once.
+    pub(crate) fn update(&mut self, chunk: &[u8]) -> io::Result<()> {
+        if !chunk.is_empty() {
+            if let Some(ref mut hasher) = self.hasher {
+                hasher.update(chunk);
+            }
+            return Ok(());
+        }
+
+        if let Some(hasher) = self.hasher.take() {
+            let digest = hasher.finish();
+            self.digest = Some(digest.clone());
+            if let Some(expected) = self.expected.take() {
+                if expected != digest {
+                    let kind = ErrorKind::DigestMismatch {
+                        expected,
+                        actual: digest,
+                    };
+                    return Err(Error::from(kind).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    #[cfg(any(feature = "digest", feature = "blake3"))]
+    fn digest(algorithm: Algorithm, data: &[u8]) -> String {
+        let mut hasher = Hasher::new(algorithm).expect("Failed to create hasher");
+        hasher.update(data);
+        hasher.finish().to_hex()
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn known_answers() {
+        let data = b"123456789";
+
+        asserting("CRC-32C check value")
+            .that(&digest(Algorithm::Crc32c, data))
+            .is_equal_to("e3069283".to_string());
+        asserting("xxHash64 of empty input")
+            .that(&digest(Algorithm::XxHash64, b""))
+            .is_equal_to("ef46db3751d8e999".to_string());
+        asserting("SHA-256")
+            .that(&digest(Algorithm::Sha256, data))
+            .is_equal_to("15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225".to_string());
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn incremental_updates_equal_single_update() {
+        let data: Vec<u8> = (0..10_000u32).map(|x| x as u8).collect();
+
+        for &algorithm in &[Algorithm::Crc32c, Algorithm::XxHash64, Algorithm::Sha256] {
+            let mut hasher = Hasher::new(algorithm).expect("Failed to create hasher");
+            for chunk in data.chunks(333) {
+                hasher.update(chunk);
+            }
+
+            asserting("Incremental digest")
+                .that(&hasher.finish().to_hex())
+                .is_equal_to(digest(algorithm, &data));
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digest_file_equals_incremental_digest() {
+        #[allow(unused_mut)]
+        let mut algorithms = vec![Algorithm::Crc32c, Algorithm::XxHash64, Algorithm::Sha256];
+        #[cfg(feature = "blake3")]
+        algorithms.push(Algorithm::Blake3);
+
+        for &size in &[0, 1000, PARALLEL_THRESHOLD + 1] {
+            let fixture = crate::testing::fixture(size, size as u64).expect("Failed to create test file");
+            let content = fixture.contents();
+
+            for &algorithm in &algorithms {
+                let actual = digest_file(fixture.path(), algorithm).expect("Failed to digest file");
+
+                asserting(&format!("Digest of {:?} for size {}", algorithm, size))
+                    .that(&actual.to_hex())
+                    .is_equal_to(digest(algorithm, &content));
+            }
+        }
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_known_answer() {
+        asserting("BLAKE3 of empty input")
+            .that(&digest(Algorithm::Blake3, b""))
+            .is_equal_to("af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262".to_string());
+    }
+
+    #[test]
+    fn from_hex() {
+        let digest = Digest::from_hex(Algorithm::Crc32c, "E3069283").expect("Failed to parse digest");
+
+        asserting("Bytes")
+            .that(&digest.as_ref())
+            .is_equal_to(&[0xe3u8, 0x06, 0x92, 0x83][..]);
+        asserting("Wrong length")
+            .that(&Digest::from_hex(Algorithm::Sha256, "e3069283"))
+            .is_none();
+        asserting("Not hex")
+            .that(&Digest::from_hex(Algorithm::Crc32c, "e30692zz"))
+            .is_none();
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn checksum_reports_mismatch_once() {
+        let expected = Digest::from_hex(Algorithm::Crc32c, "00000000").unwrap();
+        let mut checksum = Checksum::new(None, Some(expected.clone())).expect("Failed to create checksum");
+
+        checksum.update(b"123456789").expect("Failed to update");
+        let res = checksum.update(&[]);
+
+        asserting("Mismatch").that(&res.is_err()).is_true();
+        let error = Error::from(res.err().unwrap()); // Safe, bc we checked above
+        asserting("Error kind")
+            .that(error.kind())
+            .is_equal_to(&ErrorKind::DigestMismatch {
+                expected,
+                actual: Digest::from_hex(Algorithm::Crc32c, "e3069283").unwrap(),
+            });
+        asserting("Reported once").that(&checksum.update(&[]).is_ok()).is_true();
+    }
+
+    #[cfg(not(feature = "digest"))]
+    #[test]
+    fn digests_require_feature() {
+        let res = Hasher::new(Algorithm::Sha256);
+
+        asserting("Unsupported")
+            .that(&res.err().map(|e| e.kind().clone()))
+            .is_equal_to(Some(ErrorKind::Unsupported("digests require the `digest` feature")));
+    }
+
+    #[cfg(not(feature = "blake3"))]
+    #[test]
+    fn blake3_requires_feature() {
+        let res = Hasher::new(Algorithm::Blake3);
+
+        asserting("Unsupported")
+            .that(&res.err().map(|e| e.kind().clone()))
+            .is_equal_to(Some(ErrorKind::Unsupported("BLAKE3 requires the `blake3` feature")));
+    }
+}
@@ -1,71 +1,295 @@
-use failure::{Backtrace, Context, Fail};
-use std::fmt;
+use crate::digest::Digest;
+
+use std::{
+    error::Error as StdError,
+    fmt,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 /// The error kind for errors that get returned in the crate
-#[derive(Eq, PartialEq, Debug, Fail)]
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub enum ErrorKind {
     /// Memory operation failure
-    #[fail(display = "memory operation failed: {}", _0)]
     MemOpFailed(&'static str),
     /// File operation failure
-    #[fail(display = "file operation failed")]
     FileOpFailed,
     /// Libc function failure
-    #[fail(display = "libc function failed: {}", _0)]
     LibcFailed(&'static str),
+    /// User supplied buffer is unusable
+    InvalidBuffer(&'static str),
+    /// Read failure at byte `offset` of the file after `consumed` bytes have been delivered
+    ReadFailed { offset: u64, consumed: u64 },
+    /// Operation is not supported by this build or platform
+    Unsupported(&'static str),
+    /// Digest of the delivered data differs from the expected digest
+    DigestMismatch { expected: Digest, actual: Digest },
+    /// Configuration is unusable
+    InvalidConfig(&'static str),
 }
 
-impl Clone for ErrorKind {
-    fn clone(&self) -> Self {
-        use self::ErrorKind::*;
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            MemOpFailed(s) => MemOpFailed(s),
-            FileOpFailed => FileOpFailed,
-            LibcFailed(s) => LibcFailed(s),
+            ErrorKind::MemOpFailed(s) => write!(f, "memory operation failed: {}", s),
+            ErrorKind::FileOpFailed => write!(f, "file operation failed"),
+            ErrorKind::LibcFailed(s) => write!(f, "libc function failed: {}", s),
+            ErrorKind::InvalidBuffer(s) => write!(f, "invalid buffer: {}", s),
+            ErrorKind::ReadFailed { offset, consumed } => {
+                write!(f, "read failed at offset {} after {} bytes", offset, consumed)
+            }
+            ErrorKind::Unsupported(s) => write!(f, "unsupported operation: {}", s),
+            ErrorKind::InvalidConfig(s) => write!(f, "invalid configuration: {}", s),
+            ErrorKind::DigestMismatch {
+                ref expected,
+                ref actual,
+            } => write!(f, "digest mismatch: expected {}, got {}", expected, actual),
         }
     }
 }
 
-/// The error type for errors that get returned in the lookup module
-#[derive(Debug)]
+/// The error type for errors that get returned in the crate
+///
+/// Besides its kind, an error optionally carries the path of the file it refers to and the error
+/// that caused it. The causes are available via `std::error::Error::source`; `syscall` and
+/// `raw_os_error` search the whole chain for the failing libc function and its `errno`.
+#[derive(Debug, Clone)]
 pub struct Error {
-    pub(crate) inner: Context<ErrorKind>,
+    kind:   ErrorKind,
+    path:   Option<PathBuf>,
+    source: Option<Arc<dyn StdError + Send + Sync>>,
 }
 
 impl Error {
-    /// Get the kind of the error
-    pub fn kind(&self) -> &ErrorKind { self.inner.get_context() }
-}
+    /// Creates an error of `kind` caused by `source`
+    pub fn with_source<E: Into<Box<dyn StdError + Send + Sync>>>(kind: ErrorKind, source: E) -> Error {
+        Error {
+            kind,
+            path: None,
+            source: Some(Arc::from(source.into())),
+        }
+    }
+
+    /// Creates an `ErrorKind::LibcFailed` error for `syscall` caused by the current `errno`
+    ///
+    /// Call this immediately after the failed libc function to capture its `errno`.
+    pub fn last_os_error(syscall: &'static str) -> Error {
+        Error::with_source(ErrorKind::LibcFailed(syscall), io::Error::last_os_error())
+    }
 
-impl Clone for Error {
-    fn clone(&self) -> Self {
+    /// Attaches the path of the file this error refers to
+    pub fn with_path<P: AsRef<Path>>(self, path: P) -> Error {
         Error {
-            inner: Context::new(self.inner.get_context().clone()),
+            path: Some(path.as_ref().to_path_buf()),
+            ..self
         }
     }
-}
 
-impl Fail for Error {
-    fn cause(&self) -> Option<&dyn Fail> { self.inner.cause() }
+    /// Attaches `path` if it is known and the error does not refer to a path yet
+    pub(crate) fn with_known_path<P: AsRef<Path>>(self, path: Option<P>) -> Error {
+        match path {
+            Some(path) if self.path().is_none() => self.with_path(path),
+            _ => self,
+        }
+    }
 
-    fn backtrace(&self) -> Option<&Backtrace> { self.inner.backtrace() }
+    /// Get the kind of the error
+    pub fn kind(&self) -> &ErrorKind { &self.kind }
+
+    /// Get the path of the file this error or one of its causes refers to
+    pub fn path(&self) -> Option<&Path> { self.chain().filter_map(|e| e.path.as_ref()).map(|p| p.as_path()).next() }
+
+    /// Get the name of the libc function that failed, if this error has been caused by one
+    pub fn syscall(&self) -> Option<&'static str> {
+        self.chain()
+            .filter_map(|e| {
+                match e.kind {
+                    ErrorKind::LibcFailed(syscall) => Some(syscall),
+                    _ => None,
+                }
+            })
+            .next()
+    }
+
+    /// Get the innermost `std::io::Error` that caused this error
+    pub fn io_error(&self) -> Option<&io::Error> {
+        let mut io_error = None;
+        let mut source = self.source();
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<io::Error>() {
+                io_error = Some(e);
+            }
+            source = e.source();
+        }
+
+        io_error
+    }
+
+    /// Get the OS error code (`errno`) that caused this error
+    pub fn raw_os_error(&self) -> Option<i32> { self.io_error().and_then(|e| e.raw_os_error()) }
+
+    /// Iterates over this error and all errors of this crate in its chain of causes
+    fn chain(&self) -> impl Iterator<Item = &Error> {
+        let mut next = Some(self);
+        std::iter::from_fn(move || {
+            let current = next?;
+            next = current
+                .source
+                .as_ref()
+                .and_then(|source| (**source).downcast_ref::<Error>());
+            Some(current)
+        })
+    }
+
+    fn io_error_kind(&self) -> io::ErrorKind {
+        if let Some(e) = self.io_error() {
+            return e.kind();
+        }
+        match self.kind {
+            ErrorKind::InvalidBuffer(_) | ErrorKind::InvalidConfig(_) => io::ErrorKind::InvalidInput,
+            ErrorKind::DigestMismatch { .. } => io::ErrorKind::InvalidData,
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source
+            .as_ref()
+            .map(|source| &**source as &(dyn StdError + 'static))
+    }
 }
 
 impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { fmt::Display::fmt(&self.inner, f) }
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path {
+            Some(ref path) => write!(f, "{} for '{}'", self.kind, path.display()),
+            None => fmt::Display::fmt(&self.kind, f),
+        }
+    }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
         Error {
-            inner: Context::new(kind),
+            kind,
+            path: None,
+            source: None,
         }
     }
 }
 
-impl From<Context<ErrorKind>> for Error {
-    fn from(inner: Context<ErrorKind>) -> Error { Error { inner } }
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        if error.get_ref().map(|e| e.is::<Error>()).unwrap_or(false) {
+            // Safe, because we checked above that the custom error is an `Error`
+            let inner = error.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+
+        Error::with_source(ErrorKind::FileOpFailed, error)
+    }
+}
+
+/// Converts into an `std::io::Error` of the corresponding `std::io::ErrorKind`
+///
+/// The `Error` is kept as the custom error and converting back via `From<io::Error>` recovers it.
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error { io::Error::new(error.io_error_kind(), error) }
 }
 
 /// Result type for this crate
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    fn libc_error() -> Error {
+        let os_error = io::Error::from_raw_os_error(libc::EACCES);
+        let libc_error = Error::with_source(ErrorKind::LibcFailed("fcntl F_RDAHEAD"), os_error);
+        Error::with_source(ErrorKind::FileOpFailed, libc_error).with_path("/tmp/file")
+    }
+
+    #[test]
+    fn error_carries_context() {
+        let error = libc_error();
+
+        asserting("Kind")
+            .that(error.kind())
+            .is_equal_to(&ErrorKind::FileOpFailed);
+        asserting("Path")
+            .that(&error.path())
+            .is_equal_to(Some(Path::new("/tmp/file")));
+        asserting("Syscall")
+            .that(&error.syscall())
+            .is_equal_to(Some("fcntl F_RDAHEAD"));
+        asserting("Errno")
+            .that(&error.raw_os_error())
+            .is_equal_to(Some(libc::EACCES));
+        asserting("Display")
+            .that(&error.to_string())
+            .is_equal_to("file operation failed for '/tmp/file'".to_string());
+    }
+
+    #[test]
+    fn error_source_chain() {
+        let error = libc_error();
+
+        let source = error.source().expect("Missing source");
+        asserting("Source is the libc error")
+            .that(&source.to_string())
+            .is_equal_to("libc function failed: fcntl F_RDAHEAD".to_string());
+        let source = source.source().expect("Missing source of source");
+        asserting("Source of source is the io error")
+            .that(&source.downcast_ref::<io::Error>().is_some())
+            .is_true();
+    }
+
+    #[test]
+    fn error_converts_losslessly_to_io_error() {
+        let io_error = io::Error::from(libc_error());
+
+        asserting("io::ErrorKind")
+            .that(&io_error.kind())
+            .is_equal_to(io::ErrorKind::PermissionDenied);
+
+        let error = Error::from(io_error);
+        asserting("Kind")
+            .that(error.kind())
+            .is_equal_to(&ErrorKind::FileOpFailed);
+        asserting("Path")
+            .that(&error.path())
+            .is_equal_to(Some(Path::new("/tmp/file")));
+        asserting("Errno")
+            .that(&error.raw_os_error())
+            .is_equal_to(Some(libc::EACCES));
+    }
+
+    #[test]
+    fn error_without_io_error_converts_to_io_error() {
+        let io_error = io::Error::from(Error::from(ErrorKind::InvalidBuffer("Buffer is not page aligned")));
+
+        asserting("io::ErrorKind")
+            .that(&io_error.kind())
+            .is_equal_to(io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn error_last_os_error() {
+        let res = unsafe { libc::close(-1) };
+        let error = Error::last_os_error("close");
+
+        asserting("Call failed").that(&res).is_equal_to(-1);
+        asserting("Kind")
+            .that(error.kind())
+            .is_equal_to(&ErrorKind::LibcFailed("close"));
+        asserting("Errno")
+            .that(&error.raw_os_error())
+            .is_equal_to(Some(libc::EBADF));
+    }
+}
@@ -1,26 +1,44 @@
-use crate::{errors::*, os, strategy};
-
-use failure::Fail;
-use memmap::Mmap;
-use std::{fs::File, io, path::Path};
+use crate::{
+    backend::{self, Advice, Backend},
+    budget::{MemoryBudget, Reservation},
+    buffer::{self, BufferPool, BufferTuner, PageAlignedBuffer, UserBuffer},
+    decompress::{self, Compression, Decompress},
+    digest::{Algorithm, Checksum, Digest},
+    errors::*,
+    os,
+    stats::ReadStats,
+    strategy::{self, AdviceReport, StrategyReport},
+};
+
+use std::{
+    any::Any,
+    fs::File,
+    io,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    result,
+    time::Instant,
+};
 
 pub const MIN_READ_BUF_SIZE: usize = os::PAGE_SIZE;
 pub const MAX_READ_BUF_SIZE: usize = 4 * 1024 * 1024;
 
-/// Allocate a memory buffer on the stack and initialize it for the reader
+/// Allocate a memory buffer and initialize it for the reader
 ///
 /// This macro takes a `Read` as first parameter and optionally a buffer size as second parameter.
 /// If the second parameter is ommited, the buffer is allocated with size of `MAX_READ_BUF_SIZE`.
+///
+/// The buffer is an `AlignedBuf` on the heap; the macro panics if the allocation fails.
+#[deprecated(note = "Please use `fastfile::buffer::AlignedBuf` instead")]
 #[macro_export]
 macro_rules! prepare_buf {
     ($reader:ident, $size:tt) => {
-        unsafe {
-            let buf = std::mem::MaybeUninit::<[u8; $size]>::uninit();
-            buf.assume_init()
-        };
+        $crate::buffer::AlignedBuf::with_size($size).expect("Failed to allocate read buffer")
     };
     ($reader:ident) => {
-        prepare_buf!($reader, MAX_READ_BUF_SIZE)
+        $crate::buffer::AlignedBuf::with_size($crate::fastfile::MAX_READ_BUF_SIZE)
+            .expect("Failed to allocate read buffer")
     };
 }
 
@@ -30,11 +48,20 @@ pub struct FastFile {}
 impl FastFile {
     /// Open a new `FastFile` for reading similar to `std::io::File::open()`
     pub fn read<P: AsRef<Path>>(path: P) -> Result<FastFileReaderBuilder> {
-        let file = File::open(path).map_err(|e| e.context(ErrorKind::FileOpFailed))?;
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_path(path))?;
         let ff = FastFileReaderBuilder {
             file,
+            path: Some(path.to_path_buf()),
             size: None,
             size_hint: None,
+            buffer_pool: None,
+            buffer: None,
+            read_error_policy: ReadErrorPolicy::Fail,
+            decompress: Decompress::None,
+            digest: None,
+            expected_digest: None,
+            adaptive_buffer: false,
         };
 
         Ok(ff)
@@ -43,9 +70,18 @@ impl FastFile {
 
 /// `FastFileReaderBuilder` is a builder for a FastFileReader
 pub struct FastFileReaderBuilder {
-    pub file:      File,
-    pub size:      Option<usize>,
-    pub size_hint: Option<usize>,
+    pub file:              File,
+    /// Path of the file that errors refer to
+    pub path:              Option<PathBuf>,
+    pub size:              Option<usize>,
+    pub size_hint:         Option<usize>,
+    pub buffer_pool:       Option<BufferPool>,
+    pub buffer:            Option<Box<dyn UserBuffer>>,
+    pub read_error_policy: ReadErrorPolicy,
+    pub decompress:        Decompress,
+    pub digest:            Option<Algorithm>,
+    pub expected_digest:   Option<Digest>,
+    pub adaptive_buffer:   bool,
 }
 
 impl FastFileReaderBuilder {
@@ -63,177 +99,732 @@ impl FastFileReaderBuilder {
         }
     }
 
-    pub fn open_with_strategy<T: strategy::ReaderStrategy>(self, reader_strategy: &T) -> Result<FastFileReader> {
-        reader_strategy.get_reader(self)
+    /// Take the reader's buffer from `pool` and return it to `pool` when the reader is dropped
+    pub fn with_buffer_pool(self, pool: &BufferPool) -> Self {
+        FastFileReaderBuilder {
+            buffer_pool: Some(pool.clone()),
+            ..self
+        }
+    }
+
+    /// Read into `buffer` instead of a buffer allocated by the reader
+    ///
+    /// The buffer must be aligned to `os::PAGE_SIZE` and its size must be a non-zero multiple of
+    /// `os::PAGE_SIZE`; this is validated when the reader is opened. Use
+    /// `FastFileReader::into_buffer` to take the buffer back.
+    pub fn with_buffer<B: AsMut<[u8]> + Send + 'static>(self, buffer: B) -> Self {
+        FastFileReaderBuilder {
+            buffer: Some(Box::new(buffer)),
+            ..self
+        }
+    }
+
+    /// Handle failed reads of the file according to `policy`; defaults to `ReadErrorPolicy::Fail`
+    pub fn with_read_error_policy(self, policy: ReadErrorPolicy) -> Self {
+        FastFileReaderBuilder {
+            read_error_policy: policy,
+            ..self
+        }
+    }
+
+    /// Decompress the file while reading; defaults to `Decompress::None`
+    ///
+    /// The reader decodes from its backend into its read buffer, so decompressed data is available
+    /// via `FastFileRead` and `io::Read` as usual; zero-copy reads are not available then.
+    pub fn decompress(self, decompress: Decompress) -> Self { FastFileReaderBuilder { decompress, ..self } }
+
+    /// Compute the digest of all delivered data with `algorithm` while reading
+    ///
+    /// The digest is available via `FastFileReader::digest` once EOF has been reached. Opening
+    /// fails with `ErrorKind::Unsupported` if the feature `algorithm` requires is not enabled.
+    pub fn with_digest(self, algorithm: Algorithm) -> Self {
+        FastFileReaderBuilder {
+            digest: Some(algorithm),
+            ..self
+        }
+    }
+
+    /// Verify the digest of all delivered data against `digest` while reading
+    ///
+    /// The read that reaches EOF fails with `ErrorKind::DigestMismatch` if the digests differ.
+    pub fn expect_digest(self, digest: Digest) -> Self {
+        FastFileReaderBuilder {
+            expected_digest: Some(digest),
+            ..self
+        }
+    }
+
+    /// Tune the size of the read buffer by measuring the throughput of the first reads
+    ///
+    /// See `FastFileReader::set_adaptive_buffer`.
+    pub fn with_adaptive_buffer(self) -> Self {
+        FastFileReaderBuilder {
+            adaptive_buffer: true,
+            ..self
+        }
     }
 
-    pub fn open(self) -> Result<FastFileReader> {
-        let reader_strategy = strategy::DefaultReaderStrategy {};
-        self.open_with_strategy(&reader_strategy)
+    /// Creates a builder for the same file; both share the file offset
+    ///
+    /// A buffer supplied by `with_buffer` cannot be shared, so the clone has none.
+    pub fn try_clone(&self) -> Result<FastFileReaderBuilder> {
+        let file = self
+            .file
+            .try_clone()
+            .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_known_path(self.path.as_ref()))?;
+
+        Ok(self.settings().with_file(file))
+    }
+
+    /// Returns the settings of this builder apart from the file and the buffer
+    pub(crate) fn settings(&self) -> BuilderSettings {
+        BuilderSettings {
+            path:              self.path.clone(),
+            size:              self.size,
+            size_hint:         self.size_hint,
+            buffer_pool:       self.buffer_pool.clone(),
+            read_error_policy: self.read_error_policy,
+            decompress:        self.decompress,
+            digest:            self.digest,
+            expected_digest:   self.expected_digest.clone(),
+            adaptive_buffer:   self.adaptive_buffer,
+        }
+    }
+
+    pub fn open_with_strategy<T: strategy::ReaderStrategy + ?Sized>(
+        mut self,
+        reader_strategy: &T,
+    ) -> Result<FastFileReader> {
+        let path = self.path.clone();
+        let at_path = |e: Error| e.with_known_path(path.as_ref());
+        let buffer_pool = self.buffer_pool.clone();
+        let size_hint = self.size_hint;
+        let adaptive_buffer = self.adaptive_buffer;
+        let read_error_policy = self.read_error_policy;
+        let decompress = self.decompress;
+        let checksum = Checksum::new(self.digest, self.expected_digest.take())?;
+        let mut user_buffer = self.buffer.take();
+        if let Some(ref mut user_buffer) = user_buffer {
+            buffer::validate_user_buffer(user_buffer.as_mut()).map_err(at_path)?;
+        }
+
+        let mut reader = reader_strategy.get_reader(self).map_err(at_path)?;
+        reader.buffer_pool = buffer_pool;
+        // Combinators record the strategy that has actually opened the reader
+        if reader.report.strategy == strategy::UNNAMED {
+            reader.report.strategy = reader_strategy.name();
+        }
+        reader.report.size_hint = size_hint;
+        if adaptive_buffer {
+            reader.set_adaptive_buffer(true);
+        }
+        if let Some(ref mut user_buffer) = user_buffer {
+            reader.report.buffer_size = Some(user_buffer.as_mut_slice().len());
+        }
+        reader.buffer = user_buffer.map(ReadBuffer::User);
+        reader.set_read_error_policy(read_error_policy);
+        reader.decompress(decompress).map_err(at_path)?;
+        reader.checksum = checksum;
+        reader.progress.path = path;
+
+        Ok(reader)
+    }
+
+    /// Opens the reader with the default strategy
+    ///
+    /// The environment variables `FASTFILE_STRATEGY` (`file`, `mmap`, `direct`, or `auto`),
+    /// `FASTFILE_MAX_BUF`, and `FASTFILE_NO_ADVISE` override the thresholds of the default
+    /// strategy; they are read once at first use. Invalid values, and `direct` where direct I/O
+    /// is unsupported, are ignored and reported in the `StrategyReport`.
+    pub fn open(self) -> Result<FastFileReader> { self.open_with_config(strategy::StrategyConfig::default()) }
+
+    /// Like `open`, but with the thresholds of `config` before the environment overrides apply
+    pub(crate) fn open_with_config(self, config: strategy::StrategyConfig) -> Result<FastFileReader> {
+        let overrides = strategy::EnvOverrides::get();
+        let config = overrides.apply(config);
+        let reader_strategy = strategy::DefaultReaderStrategy::with_config(config);
+        let mut reader = self.open_with_strategy(&reader_strategy)?;
+        reader.report.overrides.extend(overrides.applied.iter().cloned());
+        reader.report.warnings.extend(overrides.warnings.iter().cloned());
+
+        Ok(reader)
     }
 }
 
-/// Backing Reader for FastFileReader
-pub enum BackingReader {
-    File(File),
-    Mmap(File, std::io::Cursor<Mmap>),
+/// Policy for handling failed reads of the backing file
+///
+/// With `ZeroFill` and `Skip`, a failed read is retried page by page: every page that cannot be
+/// read is recorded in `FastFileReader::failed_ranges` and reading continues after it. Failures
+/// beyond the expected file size are always returned to the caller.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReadErrorPolicy {
+    /// Return the error to the caller
+    Fail,
+    /// Record the failed page, deliver zeros in its place and continue
+    ZeroFill,
+    /// Record the failed page, leave it out and continue
+    Skip,
 }
 
-impl BackingReader {
-    pub fn file(file: File) -> Result<BackingReader> { Ok(BackingReader::File(file)) }
+/// Tracks the position of a FastFileReader and handles failed reads according to the policy
+struct ReadProgress {
+    offset:        u64,
+    consumed:      u64,
+    size:          u64,
+    policy:        ReadErrorPolicy,
+    pending_zeros: u64,
+    pending_data:  Vec<u8>,
+    failed_ranges: Vec<Range<u64>>,
+    stats:         ReadStats,
+    path:          Option<PathBuf>,
+}
 
-    pub fn mmap(file: File) -> Result<BackingReader> {
-        let mmap = unsafe { Mmap::map(&file).map_err(|e| e.context(ErrorKind::FileOpFailed))? };
-        Ok(BackingReader::Mmap(file, std::io::Cursor::new(mmap)))
+impl ReadProgress {
+    fn new(size: usize) -> ReadProgress {
+        ReadProgress {
+            offset:        0,
+            consumed:      0,
+            size:          size as u64,
+            policy:        ReadErrorPolicy::Fail,
+            pending_zeros: 0,
+            pending_data:  Vec::new(),
+            failed_ranges: Vec::new(),
+            stats:         ReadStats::default(),
+            path:          None,
+        }
+    }
+
+    fn read(&mut self, inner: &mut dyn Backend, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if !self.pending_data.is_empty() {
+                let n = self.pending_data.len().min(buf.len());
+                buf[..n].copy_from_slice(&self.pending_data[..n]);
+                self.pending_data.drain(..n);
+                self.consumed += n as u64;
+                return Ok(n);
+            }
+            if self.pending_zeros > 0 {
+                let n = (self.pending_zeros as usize).min(buf.len());
+                for x in buf[..n].iter_mut() {
+                    *x = 0;
+                }
+                self.pending_zeros -= n as u64;
+                self.consumed += n as u64;
+                return Ok(n);
+            }
+
+            let start = Instant::now();
+            let res = inner.read(buf);
+            self.stats.backend_reads += 1;
+            self.stats.io_wait += start.elapsed();
+            match res {
+                Ok(n) => {
+                    self.advance(n);
+                    return Ok(n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => self.recover(inner, e)?,
+            }
+        }
+    }
+
+    fn advance(&mut self, bytes: usize) {
+        self.offset += bytes as u64;
+        self.consumed += bytes as u64;
+    }
+
+    /// Takes back `data` that has been read but not delivered, so the next reads deliver it again
+    fn unread(&mut self, data: &[u8]) {
+        self.pending_data.splice(0..0, data.iter().cloned());
+        self.consumed -= data.len() as u64;
+    }
+
+    /// Skips the page at the current offset if the policy allows it and returns the error otherwise
+    fn recover(&mut self, inner: &mut dyn Backend, error: io::Error) -> io::Result<()> {
+        if self.policy == ReadErrorPolicy::Fail || self.offset >= self.size {
+            return Err(self.read_error(error));
+        }
+
+        let page_size = os::PAGE_SIZE as u64;
+        let end = ((self.offset / page_size + 1) * page_size).min(self.size);
+        inner.skip(end - self.offset).map_err(|e| self.read_error(e))?;
+        match self.failed_ranges.last_mut() {
+            Some(ref mut range) if range.end == self.offset => range.end = end,
+            _ => self.failed_ranges.push(self.offset..end),
+        }
+        if self.policy == ReadErrorPolicy::ZeroFill {
+            self.pending_zeros = end - self.offset;
+        }
+        self.offset = end;
+
+        Ok(())
+    }
+
+    fn read_error(&self, error: io::Error) -> io::Error {
+        let kind = ErrorKind::ReadFailed {
+            offset:   self.offset,
+            consumed: self.consumed,
+        };
+        io::Error::from(Error::with_source(kind, error).with_known_path(self.path.as_ref()))
     }
 }
 
-impl io::Read for BackingReader {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+/// Read buffer of a FastFileReader, either allocated by the reader or supplied by the user
+enum ReadBuffer {
+    Owned(PageAlignedBuffer),
+    User(Box<dyn UserBuffer>),
+}
+
+impl ReadBuffer {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
         match self {
-            BackingReader::File(file) => file.read(buf),
-            BackingReader::Mmap(_, ref mut mmap) => mmap.read(buf),
+            ReadBuffer::Owned(buffer) => buffer.as_mut_slice(),
+            ReadBuffer::User(buffer) => buffer.as_mut_slice(),
+        }
+    }
+}
+
+/// Settings of a `FastFileReaderBuilder` to create builders for other handles of the same file
+pub(crate) struct BuilderSettings {
+    path:              Option<PathBuf>,
+    size:              Option<usize>,
+    size_hint:         Option<usize>,
+    buffer_pool:       Option<BufferPool>,
+    read_error_policy: ReadErrorPolicy,
+    decompress:        Decompress,
+    digest:            Option<Algorithm>,
+    expected_digest:   Option<Digest>,
+    adaptive_buffer:   bool,
+}
+
+impl BuilderSettings {
+    /// Creates a builder with these settings for `file`
+    pub(crate) fn with_file(self, file: File) -> FastFileReaderBuilder {
+        FastFileReaderBuilder {
+            file,
+            path: self.path,
+            size: self.size,
+            size_hint: self.size_hint,
+            buffer_pool: self.buffer_pool,
+            buffer: None,
+            read_error_policy: self.read_error_policy,
+            decompress: self.decompress,
+            digest: self.digest,
+            expected_digest: self.expected_digest,
+            adaptive_buffer: self.adaptive_buffer,
         }
     }
+
+    /// Opens the file again by its path and creates a builder with these settings for it
+    pub(crate) fn reopen(self) -> Result<FastFileReaderBuilder> {
+        let file = match self.path {
+            Some(ref path) => {
+                File::open(path).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_path(path))?
+            }
+            None => return Err(ErrorKind::Unsupported("reopening a file without a path").into()),
+        };
+
+        Ok(self.with_file(file))
+    }
 }
 
 /// `FastFileReader` is a readable (`std::io::Read`) FastFile
 pub struct FastFileReader {
-    inner:  BackingReader,
-    size:   usize,
-    buffer: Option<Vec<u8>>,
-    cursor: usize,
+    inner:              Box<dyn Backend>,
+    size:               usize,
+    buffer:             Option<ReadBuffer>,
+    buffer_pool:        Option<BufferPool>,
+    buffer_reservation: Option<Reservation>,
+    budget:             &'static MemoryBudget,
+    progress:           ReadProgress,
+    compression:        Option<Compression>,
+    size_hint:          Option<u64>,
+    checksum:           Checksum,
+    report:             StrategyReport,
+    min_buffer_size:    usize,
+    max_buffer_size:    usize,
+    adaptive_buffer:    bool,
+    tuner:              Option<BufferTuner>,
 }
 
 impl FastFileReader {
-    pub fn new(inner: BackingReader, size: usize) -> FastFileReader {
+    pub fn new(inner: Box<dyn Backend>, size: usize) -> FastFileReader {
+        let report = StrategyReport::new(inner.name().to_string(), inner.is_zero_copy(), size);
         FastFileReader {
             inner,
             size,
             buffer: None,
-            cursor: 0,
+            buffer_pool: None,
+            buffer_reservation: None,
+            budget: MemoryBudget::global(),
+            progress: ReadProgress::new(size),
+            compression: None,
+            size_hint: None,
+            checksum: Checksum::default(),
+            report,
+            min_buffer_size: MIN_READ_BUF_SIZE,
+            max_buffer_size: MAX_READ_BUF_SIZE,
+            adaptive_buffer: false,
+            tuner: None,
         }
     }
 
+    /// Returns the size of the file as stored, i.e. the compressed size for compressed files
     pub fn size(&self) -> usize { self.size }
 
-    fn init_buffer(&mut self) {
-        let buf_size = optimal_buffer_size(self.size);
-        let vec = match Self::do_init_buffer(buf_size) {
-            Ok(vec) => vec,
-            Err(e) => panic!("{}", e.to_string()),
-        };
-        self.buffer = Some(vec);
+    /// Sets the limits for the size of the read buffer the reader allocates
+    ///
+    /// Both limits must be multiples of the page size; see `StrategyConfig::validate`.
+    pub fn set_buffer_sizes(&mut self, min: usize, max: usize) {
+        self.min_buffer_size = min;
+        self.max_buffer_size = max;
     }
 
-    fn do_init_buffer(buf_size: usize) -> Result<Vec<u8>> {
-        let layout = std::alloc::Layout::from_size_align(buf_size, os::PAGE_SIZE)
-            .map_err(|e| e.context(ErrorKind::MemOpFailed("Invalid memory request")))?;
-        let vec = unsafe {
-            let buf = std::alloc::alloc(layout);
-            if buf.is_null() {
-                return Err(Error::from(ErrorKind::MemOpFailed("Memory allocation request failed")));
-            }
-            Vec::from_raw_parts(buf, buf_size, buf_size)
-        };
+    /// Tunes the size of the read buffer by measuring the throughput of the first reads
+    ///
+    /// Starting from `optimal_buffer_size`, `FastFileRead::read` grows or shrinks the buffer within
+    /// the limits of `set_buffer_sizes` until the throughput stops improving. This helps if the
+    /// size is unknown or only hinted, e.g. for pipes, or if the device differs from the defaults.
+    /// `FastFileRead::read_full` reads chunks of the current size; buffers supplied by the user
+    /// are not tuned.
+    pub fn set_adaptive_buffer(&mut self, adaptive: bool) { self.adaptive_buffer = adaptive; }
+
+    /// Computes the optimal buffer size for the file within the limits of this reader
+    pub fn optimal_buffer_size(&self) -> usize {
+        optimal_buffer_size_between(self.size, self.min_buffer_size, self.max_buffer_size)
+    }
+
+    /// Returns whether reads hand out slices of the backend instead of copying into a read buffer
+    pub fn is_zero_copy(&self) -> bool { self.inner.is_zero_copy() }
 
-        Ok(vec)
+    /// Returns the digest of all delivered data once EOF has been reached
+    ///
+    /// Requires `FastFileReaderBuilder::with_digest` or `FastFileReaderBuilder::expect_digest`.
+    pub fn digest(&self) -> Option<&Digest> { self.checksum.digest() }
+
+    /// Returns the compression format of the file if the reader decompresses it
+    pub fn compression(&self) -> Option<Compression> { self.compression }
+
+    /// Returns the size of the data delivered by the reader if it is known
+    ///
+    /// For uncompressed files this is `size`; for compressed files it is unknown.
+    pub fn uncompressed_size(&self) -> Option<u64> {
+        match self.compression {
+            Some(_) => None,
+            None => Some(self.size as u64),
+        }
     }
 
-    fn file_read(&mut self) -> io::Result<&[u8]> {
-        use std::io::Read;
+    /// Returns an estimate of the size of the data delivered by the reader
+    ///
+    /// For gzip it is read from the trailer of the file, which only covers the last member and
+    /// stores its size modulo 2^32. So the hint is exact for single member files of less than
+    /// 4 GiB, but too small for files of several members, as written by `cat` or by log
+    /// rotation; never rely on it to size a buffer exactly. For zstd there is no hint.
+    pub fn uncompressed_size_hint(&self) -> Option<u64> {
+        match self.compression {
+            Some(_) => self.size_hint,
+            None => Some(self.size as u64),
+        }
+    }
 
-        if self.buffer.is_none() {
-            self.init_buffer();
+    /// Returns the byte ranges of the file that could not be read
+    ///
+    /// Ranges are only recorded with `ReadErrorPolicy::ZeroFill` or `ReadErrorPolicy::Skip`.
+    pub fn failed_ranges(&self) -> &[Range<u64>] { &self.progress.failed_ranges }
+
+    /// Returns statistics about the reads and the I/O this reader has done so far
+    pub fn stats(&self) -> ReadStats {
+        ReadStats {
+            bytes_delivered: self.progress.consumed,
+            page_faults: self.inner.page_faults(),
+            ..self.progress.stats
         }
-        let vec = self.buffer.as_mut().unwrap(); // Safe, bc we checked above
-        let buf = vec.as_mut_slice();
+    }
 
-        let n = self.inner.read(&mut buf[..])?;
+    /// Returns the inputs and decisions of the strategy that opened this reader
+    pub fn report(&self) -> &StrategyReport { &self.report }
+
+    /// Returns the report for strategies to record the inputs they consider
+    pub fn report_mut(&mut self) -> &mut StrategyReport { &mut self.report }
+
+    /// Passes on `advice` about the expected access pattern to the backend
+    ///
+    /// The advice and its result are recorded in the `StrategyReport`.
+    pub fn advise(&mut self, advice: Advice) -> Result<()> {
+        let start = Instant::now();
+        let res = self.inner.advise(advice);
+        self.progress.stats.advise_calls += 1;
+        self.progress.stats.io_wait += start.elapsed();
+        self.report.advice.push(AdviceReport {
+            advice,
+            error: res.as_ref().err().map(|e| e.to_string()),
+        });
+
+        res.map_err(|e| e.with_known_path(self.progress.path.as_ref()))
+    }
 
-        Ok(&buf[0..n])
+    fn decompress(&mut self, decompress: Decompress) -> Result<()> {
+        let inner = mem::replace(&mut self.inner, Box::new(backend::Detached));
+        let decompression = decompress::decompress(inner, decompress)?;
+        self.inner = decompression.backend;
+        self.compression = decompression.compression;
+        self.size_hint = decompression.uncompressed_size_hint;
+        self.report.zero_copy = self.inner.is_zero_copy();
+        if let Some(compression) = self.compression {
+            self.report.backend = format!("{:?} decoder over {}", compression, self.report.backend);
+            // Offsets refer to the decompressed data, so failed reads cannot be skipped
+            self.progress.size = 0;
+        }
+
+        Ok(())
     }
 
-    fn file_read_to_end(&mut self) -> io::Result<&[u8]> {
-        use std::io::Read;
+    pub(crate) fn set_read_error_policy(&mut self, policy: ReadErrorPolicy) { self.progress.policy = policy; }
+
+    /// Takes back the buffer supplied by `FastFileReaderBuilder::with_buffer`
+    ///
+    /// If the buffer is not of type `B`, it is returned as `Err` to be downcast to its actual
+    /// type. If no buffer has been supplied, `Err` holds `()`.
+    pub fn into_buffer<B: 'static>(mut self) -> result::Result<B, Box<dyn Any + Send>> {
+        match self.buffer.take() {
+            Some(ReadBuffer::User(buffer)) => buffer.into_any().downcast::<B>().map(|b| *b),
+            buffer => {
+                self.buffer = buffer;
+                Err(Box::new(()))
+            }
+        }
+    }
 
+    fn init_buffer(&mut self) -> Result<()> {
         if self.buffer.is_none() {
-            self.init_buffer();
+            let reservation = self
+                .budget
+                .reserve_between(self.min_buffer_size, self.optimal_buffer_size())
+                .ok_or(ErrorKind::MemOpFailed("Memory budget exhausted"))?;
+            let buf_size = reservation.bytes();
+            self.buffer_reservation = Some(reservation);
+            self.report.buffer_size = Some(buf_size);
+            let buffer = match self.buffer_pool {
+                Some(ref pool) => pool.get(buf_size)?,
+                None => PageAlignedBuffer::new(buf_size)?,
+            };
+            self.buffer = Some(ReadBuffer::Owned(buffer));
+            if self.adaptive_buffer {
+                self.tuner = Some(BufferTuner::new(buf_size, self.min_buffer_size, self.max_buffer_size));
+            }
         }
-        let mut vec = self.buffer.as_mut().unwrap(); // Safe, bc we checked above
 
-        // `Read::read_to_end` _appends_ to the specified buffer; so we need to set the len to 0
-        // first
-        unsafe {
-            vec.set_len(0);
+        Ok(())
+    }
+
+    /// Grows the read buffer to the size chosen by the tuner
+    fn adapt_buffer(&mut self) {
+        if let Some(ref mut tuner) = self.tuner {
+            if let Some(ReadBuffer::Owned(ref mut buffer)) = self.buffer {
+                let size = tuner.size();
+                if !tuner.is_settled() && size > buffer.capacity() {
+                    let grown = match self.buffer_reservation {
+                        Some(ref mut reservation) => reservation.grow(size),
+                        None => Ok(()),
+                    }
+                    .and_then(|_| buffer.grow(size));
+                    if grown.is_err() {
+                        tuner.settle_at(buffer.capacity());
+                    }
+                }
+            }
+            self.report.buffer_size = Some(tuner.size());
         }
+    }
 
-        let n = self.inner.read_to_end(&mut vec)?;
-        let buf = vec.as_mut_slice();
+    fn buffered_read(&mut self) -> io::Result<&[u8]> {
+        self.init_buffer()?;
+        self.adapt_buffer();
+        let len = self.tuner.as_ref().map(BufferTuner::size);
+        let buffer = self.buffer.as_mut().unwrap(); // Safe, bc we initialized it above
+        let buf = buffer.as_mut_slice();
+        // The tuner may ask for more than the buffer holds if growing it failed
+        let buf = match len {
+            Some(len) => {
+                let len = len.min(buf.len());
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+
+        let io_wait = self.progress.stats.io_wait;
+        let n = self.progress.read(&mut *self.inner, buf)?;
+        if let Some(ref mut tuner) = self.tuner {
+            tuner.record(n, self.progress.stats.io_wait - io_wait);
+        }
+        self.checksum.update(&buf[0..n])?;
 
         Ok(&buf[0..n])
     }
 
-    fn mmap_read(&mut self) -> io::Result<&[u8]> {
-        match self.inner {
-            BackingReader::File(_) => unimplemented!(),
-            BackingReader::Mmap(_, ref mmap) => {
-                let mmap = mmap.get_ref();
-                let opt_size = optimal_buffer_size(self.size);
-                let mut end = self.cursor + opt_size;
-                if end > mmap.len() {
-                    end = mmap.len();
-                }
-                let buf = &mmap[self.cursor..end];
-                self.cursor = end;
-                Ok(buf)
+    fn buffered_read_full(&mut self) -> io::Result<&[u8]> {
+        self.init_buffer()?;
+        self.adapt_buffer();
+        let len = self.tuner.as_ref().map(BufferTuner::size);
+        let buffer = self.buffer.as_mut().unwrap(); // Safe, bc we initialized it above
+        let buf = buffer.as_mut_slice();
+        // The tuner may ask for more than the buffer holds if growing it failed
+        let buf = match len {
+            Some(len) => {
+                let len = len.min(buf.len());
+                &mut buf[..len]
+            }
+            None => buf,
+        };
+
+        let mut len = 0usize;
+        while len < buf.len() {
+            match self.progress.read(&mut *self.inner, &mut buf[len..])? {
+                0 => break,
+                n => len += n,
             }
         }
+        self.checksum.update(&buf[0..len])?;
+
+        Ok(&buf[0..len])
     }
 
-    fn mmap_read_to_end(&mut self) -> io::Result<&[u8]> {
-        match self.inner {
-            BackingReader::File(_) => unimplemented!(),
-            BackingReader::Mmap(_, ref mmap) => {
-                let mmap = mmap.get_ref();
-                Ok(&mmap[..])
+    fn buffered_read_to_end(&mut self) -> io::Result<&[u8]> {
+        self.init_buffer()?;
+        let buffer = self.buffer.as_mut().unwrap(); // Safe, bc we initialized it above
+
+        let mut len = 0usize;
+        loop {
+            if len == buffer.as_mut_slice().len() {
+                match buffer {
+                    ReadBuffer::Owned(buffer) => {
+                        if let Some(ref mut reservation) = self.buffer_reservation {
+                            reservation.grow(2 * len)?;
+                        }
+                        buffer.grow(2 * len)?
+                    }
+                    // A user buffer cannot grow, so it is only sufficient if we are at EOF already
+                    ReadBuffer::User(_) => {
+                        let mut probe = [0u8; 1];
+                        match self.progress.read(&mut *self.inner, &mut probe) {
+                            Ok(0) => break,
+                            Ok(_) => {
+                                // Nothing is lost; the next reads deliver the data again
+                                self.progress.unread(&probe);
+                                self.progress.unread(&buffer.as_mut_slice()[..len]);
+                                return Err(io::Error::other("user supplied buffer is too small to read to end"));
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            }
+            match self.progress.read(&mut *self.inner, &mut buffer.as_mut_slice()[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(e) => return Err(e),
             }
         }
+
+        let buf = &buffer.as_mut_slice()[0..len];
+        self.checksum.update(buf)?;
+        self.checksum.update(&[])?;
+
+        Ok(buf)
+    }
+
+    fn slice_read(&mut self, max_len: usize, to_end: bool) -> io::Result<&[u8]> {
+        let buf = self.inner.read_slice(max_len)?;
+        self.progress.stats.backend_reads += 1;
+        self.progress.advance(buf.len());
+        self.checksum.update(buf)?;
+        if to_end {
+            self.checksum.update(&[])?;
+        }
+
+        Ok(buf)
+    }
+}
+
+impl Drop for FastFileReader {
+    fn drop(&mut self) {
+        if let (Some(pool), Some(ReadBuffer::Owned(buffer))) = (self.buffer_pool.as_ref(), self.buffer.take()) {
+            pool.put(buffer);
+        }
     }
 }
 
 /// Computes the optimal buffer size for a specified file size aligned to the system's page size.
 pub fn optimal_buffer_size(file_size: usize) -> usize {
-    let size = file_size as usize;
-    let suggestion = ((size + os::PAGE_SIZE - 1) / os::PAGE_SIZE) * os::PAGE_SIZE;
-    let suggestion = MAX_READ_BUF_SIZE.min(suggestion);
-    MIN_READ_BUF_SIZE.max(suggestion)
+    optimal_buffer_size_between(file_size, MIN_READ_BUF_SIZE, MAX_READ_BUF_SIZE)
+}
+
+/// Computes the optimal buffer size for a specified file size aligned to the system's page size
+/// between `min` and `max`.
+pub fn optimal_buffer_size_between(file_size: usize, min: usize, max: usize) -> usize {
+    let suggestion = file_size.div_ceil(os::PAGE_SIZE) * os::PAGE_SIZE;
+    let suggestion = max.min(suggestion);
+    min.max(suggestion)
 }
 
 pub trait FastFileRead {
+    /// Reads the next chunk of the file; an empty chunk signals EOF
+    ///
+    /// Interrupted reads are retried internally, but a chunk may be shorter than the read buffer.
     fn read(&mut self) -> io::Result<&[u8]>;
 
+    /// Reads the next chunk of the file and keeps reading until the read buffer is full or EOF
+    ///
+    /// All chunks but the last one have the same size; an empty chunk signals EOF. The default
+    /// implementation delivers the chunks of `read` as they are, so implementors that can refill
+    /// their buffer should override it.
+    fn read_full(&mut self) -> io::Result<&[u8]> { self.read() }
+
+    /// Reads the remainder of the file
     fn read_to_end(&mut self) -> io::Result<&[u8]>;
 }
 
 impl FastFileRead for FastFileReader {
     fn read(&mut self) -> io::Result<&[u8]> {
-        match self.inner {
-            BackingReader::File(_) => self.file_read(),
-            BackingReader::Mmap(..) => self.mmap_read(),
+        self.progress.stats.fast_reads += 1;
+        if self.inner.is_zero_copy() {
+            self.slice_read(self.optimal_buffer_size(), false)
+        } else {
+            self.buffered_read()
+        }
+    }
+
+    fn read_full(&mut self) -> io::Result<&[u8]> {
+        self.progress.stats.fast_reads += 1;
+        if self.inner.is_zero_copy() {
+            // Slices are always complete
+            self.slice_read(self.optimal_buffer_size(), false)
+        } else {
+            self.buffered_read_full()
         }
     }
 
     fn read_to_end(&mut self) -> io::Result<&[u8]> {
-        match self.inner {
-            BackingReader::File(_) => self.file_read_to_end(),
-            BackingReader::Mmap(..) => self.mmap_read_to_end(),
+        self.progress.stats.fast_reads += 1;
+        if self.inner.is_zero_copy() {
+            self.slice_read(usize::MAX, true)
+        } else {
+            self.buffered_read_to_end()
         }
     }
 }
 
 impl io::Read for FastFileReader {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { self.inner.read(buf) }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.progress.read(&mut *self.inner, buf)?;
+        // An empty `buf` does not signal EOF
+        if !buf.is_empty() {
+            self.checksum.update(&buf[..n])?;
+        }
+
+        Ok(n)
+    }
 }
 
 #[cfg(test)]
@@ -242,15 +833,19 @@ mod tests {
         optimal_buffer_size,
         os::PAGE_SIZE,
         strategy,
-        BackingReader,
         FastFile,
         FastFileReader,
-        FastFileReaderBuilder,
         Result,
         MAX_READ_BUF_SIZE,
         MIN_READ_BUF_SIZE,
     };
 
+    use crate::{
+        backend::FileBackend,
+        buffer::AlignedBuf,
+        testing::{self, FileStrategy, MmapStrategy},
+    };
+
     use rand::{rngs::SmallRng, Rng, SeedableRng};
     use ring::digest::{Context, Digest, SHA256};
 
@@ -321,13 +916,13 @@ mod tests {
 
         #[test]
         fn fastfilereader_read_correctly_with_file_backend() {
-            let reader_strategy = TestFileReaderStragegy {};
+            let reader_strategy = FileStrategy;
             fastfilereader_reads_correctly_tester(&reader_strategy);
         }
 
         #[test]
         fn fastfilereader_read_correctly_with_mmap_backend() {
-            let reader_strategy = TestMmapReaderStragegy {};
+            let reader_strategy = MmapStrategy;
             fastfilereader_reads_correctly_tester(&reader_strategy);
         }
 
@@ -335,7 +930,7 @@ mod tests {
             verify_reader(reader_strategy, |ffr: &mut FastFileReader| {
                 let mut len = 0usize;
                 let mut digest = Context::new(&SHA256);
-                let mut buf = prepare_buf!(ffr, 4096);
+                let mut buf = AlignedBuf::with_size(4096).expect("Failed to allocate buffer");
                 loop {
                     let n = ffr.read(&mut buf).expect("Failed to fastread file");
                     if n == 0 {
@@ -357,13 +952,13 @@ mod tests {
 
         #[test]
         fn fastfilereader_reads_correctly_with_file_backend() {
-            let reader_strategy = TestFileReaderStragegy {};
+            let reader_strategy = FileStrategy;
             fastfilereader_reads_correctly_tester(&reader_strategy);
         }
 
         #[test]
         fn fastfilereader_reads_correctly_with_mmap_backend() {
-            let reader_strategy = TestMmapReaderStragegy {};
+            let reader_strategy = MmapStrategy;
             fastfilereader_reads_correctly_tester(&reader_strategy);
         }
 
@@ -385,6 +980,925 @@ mod tests {
         }
     }
 
+    mod fast_read_full {
+        use super::*;
+
+        use crate::fastfile::FastFileRead;
+
+        #[test]
+        fn fastfilereader_reads_full_chunks_correctly_with_file_backend() {
+            let reader_strategy = FileStrategy;
+            fastfilereader_reads_full_chunks_correctly_tester(&reader_strategy);
+        }
+
+        #[test]
+        fn fastfilereader_reads_full_chunks_correctly_with_mmap_backend() {
+            let reader_strategy = MmapStrategy;
+            fastfilereader_reads_full_chunks_correctly_tester(&reader_strategy);
+        }
+
+        fn fastfilereader_reads_full_chunks_correctly_tester<T: strategy::ReaderStrategy>(reader_strategy: &T) {
+            verify_reader(reader_strategy, |ffr: &mut FastFileReader| {
+                let mut chunks = Vec::new();
+                let mut digest = Context::new(&SHA256);
+                loop {
+                    let buf = ffr.read_full().expect("Failed to fastread file");
+                    if buf.is_empty() {
+                        break;
+                    };
+                    chunks.push(buf.len());
+                    digest.update(buf);
+                }
+                if let Some((_, full_chunks)) = chunks.split_last() {
+                    asserting("All chunks but the last are full")
+                        .that(&full_chunks.iter().all(|len| *len == chunks[0]))
+                        .is_true();
+                }
+                let digest = digest.finish();
+                (chunks.iter().sum(), digest)
+            });
+        }
+
+        #[test]
+        fn read_full_defaults_to_read() {
+            struct Chunk(Vec<u8>);
+            impl FastFileRead for Chunk {
+                fn read(&mut self) -> std::io::Result<&[u8]> { Ok(&self.0) }
+
+                fn read_to_end(&mut self) -> std::io::Result<&[u8]> { Ok(&self.0) }
+            }
+            let mut chunk = Chunk(vec![1, 2, 3]);
+
+            let buf = chunk.read_full().expect("Failed to read chunk");
+
+            asserting("Chunk of read").that(&buf).is_equal_to(&[1u8, 2, 3][..]);
+        }
+    }
+
+    mod fast_read_to_end {
+        use super::*;
+
+        use crate::fastfile::FastFileRead;
+
+        #[test]
+        fn fastfilereader_reads_to_end_correctly_with_file_backend() {
+            let reader_strategy = FileStrategy;
+            fastfilereader_reads_to_end_correctly_tester(&reader_strategy);
+        }
+
+        #[test]
+        fn fastfilereader_reads_to_end_correctly_with_mmap_backend() {
+            let reader_strategy = MmapStrategy;
+            fastfilereader_reads_to_end_correctly_tester(&reader_strategy);
+        }
+
+        fn fastfilereader_reads_to_end_correctly_tester<T: strategy::ReaderStrategy>(reader_strategy: &T) {
+            verify_reader(reader_strategy, |ffr: &mut FastFileReader| {
+                let mut digest = Context::new(&SHA256);
+                let buf = ffr.read_to_end().expect("Failed to fastread file to end");
+                digest.update(buf);
+                let digest = digest.finish();
+                (buf.len(), digest)
+            });
+        }
+    }
+
+    mod read_errors {
+        use super::*;
+
+        use crate::{
+            errors::{Error, ErrorKind},
+            fastfile::{FastFileRead, ReadErrorPolicy},
+        };
+        use std::{fs::File, io::Read};
+
+        // Reading a directory fails with `EISDIR` for every page
+        fn failing_reader(size: usize, policy: ReadErrorPolicy) -> FastFileReader {
+            let file = File::open("src").expect("Failed to open directory");
+            let inner = Box::new(FileBackend::new(file));
+            let mut ffr = FastFileReader::new(inner, size);
+            ffr.progress.policy = policy;
+            ffr
+        }
+
+        fn read_failed_at(error: std::io::Error) -> ErrorKind { Error::from(error).kind().clone() }
+
+        #[test]
+        fn read_error_carries_offset_and_consumed_bytes() {
+            let mut ffr = failing_reader(2 * PAGE_SIZE, ReadErrorPolicy::Fail);
+
+            let res = FastFileRead::read(&mut ffr).map(|buf| buf.len());
+
+            asserting("Read fails").that(&res.is_err()).is_true();
+            let error = Error::from(res.err().unwrap()); // Safe, bc we checked above
+            asserting("Error kind")
+                .that(error.kind())
+                .is_equal_to(&ErrorKind::ReadFailed {
+                    offset:   0,
+                    consumed: 0,
+                });
+            asserting("Errno")
+                .that(&error.raw_os_error())
+                .is_equal_to(Some(libc::EISDIR));
+            asserting("No failed ranges")
+                .that(&ffr.failed_ranges().is_empty())
+                .is_true();
+        }
+
+        #[test]
+        fn read_error_carries_path() {
+            let mut ffr = FastFile::read("src")
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let res = FastFileRead::read(&mut ffr).map(|buf| buf.len());
+
+            let error = Error::from(res.expect_err("Read succeeded"));
+            asserting("Path")
+                .that(&error.path())
+                .is_equal_to(Some(std::path::Path::new("src")));
+        }
+
+        #[test]
+        fn strategy_error_carries_path() {
+            let res = FastFile::read("src")
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&MmapStrategy);
+
+            let error = res.err().expect("Mapping a directory succeeded");
+            asserting("Path")
+                .that(&error.path())
+                .is_equal_to(Some(std::path::Path::new("src")));
+        }
+
+        #[test]
+        fn read_error_policy_zero_fill() {
+            let size = 2 * PAGE_SIZE + 1;
+            let mut ffr = failing_reader(size, ReadErrorPolicy::ZeroFill);
+
+            let mut len = 0usize;
+            let error = loop {
+                match FastFileRead::read(&mut ffr) {
+                    Ok(buf) => {
+                        asserting("Zero filled").that(&buf.iter().all(|x| *x == 0)).is_true();
+                        len += buf.len();
+                    }
+                    Err(e) => break e,
+                }
+            };
+
+            asserting("Zero filled bytes").that(&len).is_equal_to(size);
+            asserting("Failed ranges")
+                .that(&ffr.failed_ranges().to_vec())
+                .is_equal_to(vec![0..size as u64]);
+            asserting("Error beyond file size")
+                .that(&read_failed_at(error))
+                .is_equal_to(ErrorKind::ReadFailed {
+                    offset:   size as u64,
+                    consumed: size as u64,
+                });
+        }
+
+        #[test]
+        fn read_error_policy_skip() {
+            let size = 3 * PAGE_SIZE;
+            let mut ffr = failing_reader(size, ReadErrorPolicy::Skip);
+            let mut buf = AlignedBuf::with_size(PAGE_SIZE).expect("Failed to allocate buffer");
+
+            let res = Read::read(&mut ffr, &mut buf);
+
+            asserting("Read fails after skipping").that(&res.is_err()).is_true();
+            asserting("Failed ranges")
+                .that(&ffr.failed_ranges().to_vec())
+                .is_equal_to(vec![0..size as u64]);
+            asserting("Error beyond file size")
+                .that(&read_failed_at(res.err().unwrap())) // Safe, bc we checked above
+                .is_equal_to(ErrorKind::ReadFailed {
+                    offset:   size as u64,
+                    consumed: 0,
+                });
+        }
+    }
+
+    mod user_buffer {
+        use super::*;
+
+        use crate::{errors::ErrorKind, fastfile::FastFileRead};
+
+        #[test]
+        fn fastfilereader_reads_into_user_buffer() {
+            let fixture = testing::fixture(3 * PAGE_SIZE + 1, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let buffer = AlignedBuf::with_size(PAGE_SIZE).expect("Failed to allocate buffer");
+            let ptr = buffer.as_ptr();
+            let mut ffr = FastFile::read(&path)
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(buffer)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let buf = ffr.read().expect("Failed to fastread file");
+            asserting("Read into user buffer").that(&buf.as_ptr()).is_equal_to(ptr);
+            asserting("Read size").that(&buf.len()).is_equal_to(PAGE_SIZE);
+
+            let buffer: AlignedBuf = ffr.into_buffer().expect("Failed to take back buffer");
+            asserting("Took back user buffer")
+                .that(&buffer.as_ptr())
+                .is_equal_to(ptr);
+        }
+
+        #[test]
+        fn fastfilereader_reads_to_end_into_sufficient_user_buffer() {
+            let fixture = testing::fixture(2 * PAGE_SIZE, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let buffer = AlignedBuf::with_size(2 * PAGE_SIZE).expect("Failed to allocate buffer");
+            let mut ffr = FastFile::read(&path)
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(buffer)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let buf = ffr.read_to_end().expect("Failed to fastread file to end");
+
+            asserting("Read size").that(&buf.len()).is_equal_to(2 * PAGE_SIZE);
+        }
+
+        #[test]
+        fn fastfilereader_reads_to_end_into_too_small_user_buffer() {
+            let fixture = testing::fixture(2 * PAGE_SIZE, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let buffer = AlignedBuf::with_size(PAGE_SIZE).expect("Failed to allocate buffer");
+            let mut ffr = FastFile::read(&path)
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(buffer)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let res = ffr.read_to_end().map(|buf| buf.len());
+            let mut contents = Vec::new();
+            loop {
+                let buf = ffr.read().expect("Failed to fastread file");
+                if buf.is_empty() {
+                    break;
+                }
+                contents.extend_from_slice(buf);
+            }
+
+            asserting("Read to end fails").that(&res.is_err()).is_true();
+            asserting("Later reads deliver all data")
+                .that(&(contents == fixture.contents()))
+                .is_true();
+        }
+
+        #[test]
+        fn fastfilereader_returns_user_buffer_of_other_type() {
+            let buffer = AlignedBuf::with_size(PAGE_SIZE).expect("Failed to allocate buffer");
+            let ptr = buffer.as_ptr();
+            let ffr = FastFile::read("Cargo.toml")
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(buffer)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let res = ffr.into_buffer::<Vec<u8>>();
+
+            let buffer = res
+                .err()
+                .and_then(|buffer| buffer.downcast::<AlignedBuf>().ok())
+                .expect("Failed to take back buffer");
+            asserting("Took back user buffer")
+                .that(&buffer.as_ptr())
+                .is_equal_to(ptr);
+        }
+
+        #[test]
+        fn fastfilereader_rejects_unaligned_user_buffer() {
+            struct Unaligned(AlignedBuf);
+            impl AsMut<[u8]> for Unaligned {
+                fn as_mut(&mut self) -> &mut [u8] { &mut self.0[1..] }
+            }
+            let buffer = AlignedBuf::with_size(PAGE_SIZE + 1).expect("Failed to allocate buffer");
+            let res = FastFile::read("Cargo.toml")
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(Unaligned(buffer))
+                .open_with_strategy(&FileStrategy);
+
+            asserting("Open fails").that(&res.is_err()).is_true();
+            let err = res.err().unwrap(); // Safe, bc we checked above
+            asserting("Error kind")
+                .that(err.kind())
+                .is_equal_to(&ErrorKind::InvalidBuffer("Buffer is not page aligned"));
+        }
+
+        #[test]
+        fn fastfilereader_rejects_user_buffer_of_invalid_size() {
+            let buffer = AlignedBuf::with_size(PAGE_SIZE + 1).expect("Failed to allocate buffer");
+            let res = FastFile::read("Cargo.toml")
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_buffer(buffer)
+                .open_with_strategy(&FileStrategy);
+
+            asserting("Open fails").that(&res.is_err()).is_true();
+        }
+    }
+
+    mod decompress {
+        use super::*;
+
+        use crate::{
+            decompress::{Compression, Decompress},
+            fastfile::FastFileRead,
+        };
+        use std::io::{Read, Write};
+
+        fn compressed_fixture(compression: Compression, content: &[u8]) -> tempfile::NamedTempFile {
+            let compressed = match compression {
+                Compression::Gzip => {
+                    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                    encoder.write_all(content).expect("Failed to compress");
+                    encoder.finish().expect("Failed to compress")
+                }
+                #[cfg(feature = "zstd")]
+                Compression::Zstd => zstd::encode_all(content, 0).expect("Failed to compress"),
+                #[cfg(not(feature = "zstd"))]
+                Compression::Zstd => unreachable!(),
+            };
+            let mut file = tempfile::NamedTempFile::new().expect("Failed to create test file");
+            file.write_all(&compressed).expect("Failed to write test file");
+            file
+        }
+
+        fn fastfilereader_decompresses_tester<T: strategy::ReaderStrategy>(
+            reader_strategy: &T,
+            compression: Compression,
+        ) {
+            let content = testing::fixture_bytes(3 * MAX_READ_BUF_SIZE + 1, 1);
+            let file = compressed_fixture(compression, &content);
+            let compressed_size = file.as_file().metadata().unwrap().len() as usize;
+
+            let mut ffr = FastFile::read(file.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .decompress(Decompress::Auto)
+                .open_with_strategy(reader_strategy)
+                .expect("Failed to open path as FastFile");
+
+            asserting("Compression")
+                .that(&ffr.compression())
+                .is_equal_to(Some(compression));
+            asserting("Size").that(&ffr.size()).is_equal_to(compressed_size);
+            let first = ffr.read_full().expect("Failed to fastread file").to_vec();
+            let mut rest = Vec::new();
+            Read::read_to_end(&mut ffr, &mut rest).expect("Failed to read file");
+            asserting("Content").that(&[first, rest].concat()).is_equal_to(&content);
+        }
+
+        #[test]
+        fn fastfilereader_decompresses_gzip_with_file_backend() {
+            fastfilereader_decompresses_tester(&FileStrategy, Compression::Gzip);
+        }
+
+        #[test]
+        fn fastfilereader_decompresses_gzip_with_mmap_backend() {
+            fastfilereader_decompresses_tester(&MmapStrategy, Compression::Gzip);
+        }
+
+        #[cfg(feature = "zstd")]
+        #[test]
+        fn fastfilereader_decompresses_zstd_with_file_backend() {
+            fastfilereader_decompresses_tester(&FileStrategy, Compression::Zstd);
+        }
+
+        #[test]
+        fn fastfilereader_reports_uncompressed_size_hint() {
+            let content = testing::fixture_bytes(100_000, 2);
+            let file = compressed_fixture(Compression::Gzip, &content);
+
+            let ffr = FastFile::read(file.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .decompress(Decompress::Gzip)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            asserting("Uncompressed size").that(&ffr.uncompressed_size()).is_none();
+            asserting("Uncompressed size hint")
+                .that(&ffr.uncompressed_size_hint())
+                .is_equal_to(Some(100_000));
+        }
+
+        #[test]
+        fn fastfilereader_reads_uncompressed_file_as_is() {
+            let fixture = testing::fixture(100_000, 3).expect("Failed to create test file");
+
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .decompress(Decompress::Auto)
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+
+            asserting("Compression").that(&ffr.compression()).is_none();
+            asserting("Uncompressed size")
+                .that(&ffr.uncompressed_size())
+                .is_equal_to(Some(100_000));
+            asserting("Content")
+                .that(
+                    &FastFileRead::read_to_end(&mut ffr)
+                        .expect("Failed to fastread file")
+                        .to_vec(),
+                )
+                .is_equal_to(fixture.contents());
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    mod digest {
+        use super::*;
+
+        use crate::{
+            digest::{self, Algorithm, Hasher},
+            errors::{Error, ErrorKind},
+            fastfile::FastFileRead,
+        };
+        use std::io::Read;
+
+        fn expected(algorithm: Algorithm, content: &[u8]) -> digest::Digest {
+            let mut hasher = Hasher::new(algorithm).expect("Failed to create hasher");
+            hasher.update(content);
+            hasher.finish()
+        }
+
+        fn fastfilereader_computes_digest_tester<T: strategy::ReaderStrategy>(reader_strategy: &T) {
+            let fixture = testing::fixture(2 * MAX_READ_BUF_SIZE + 1, 1).expect("Failed to create test file");
+            let content = fixture.contents();
+
+            for &algorithm in &[Algorithm::Crc32c, Algorithm::XxHash64, Algorithm::Sha256] {
+                let mut ffr = FastFile::read(fixture.path())
+                    .expect("Failed to create FastFileReaderBuilder")
+                    .with_digest(algorithm)
+                    .open_with_strategy(reader_strategy)
+                    .expect("Failed to open path as FastFile");
+
+                let first = ffr.read_full().expect("Failed to fastread file").len();
+                asserting("No digest before EOF").that(&ffr.digest()).is_none();
+                let mut rest = Vec::new();
+                Read::read_to_end(&mut ffr, &mut rest).expect("Failed to read file");
+
+                asserting("Read bytes")
+                    .that(&(first + rest.len()))
+                    .is_equal_to(content.len());
+                asserting("Digest")
+                    .that(&ffr.digest())
+                    .is_equal_to(Some(&expected(algorithm, &content)));
+            }
+        }
+
+        #[test]
+        fn fastfilereader_computes_digest_with_file_backend() { fastfilereader_computes_digest_tester(&FileStrategy); }
+
+        #[test]
+        fn fastfilereader_computes_digest_with_mmap_backend() { fastfilereader_computes_digest_tester(&MmapStrategy); }
+
+        #[test]
+        fn fastfilereader_computes_digest_with_read_to_end() {
+            let fixture = testing::fixture(100_000, 2).expect("Failed to create test file");
+
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_digest(Algorithm::Crc32c)
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+            FastFileRead::read_to_end(&mut ffr).expect("Failed to read to end");
+
+            asserting("Digest")
+                .that(&ffr.digest())
+                .is_equal_to(Some(&expected(Algorithm::Crc32c, &fixture.contents())));
+        }
+
+        #[test]
+        fn fastfilereader_fails_on_digest_mismatch_at_eof() {
+            let fixture = testing::fixture(100_000, 3).expect("Failed to create test file");
+            let wrong = expected(Algorithm::XxHash64, b"something else");
+
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .expect_digest(wrong.clone())
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+            let res = loop {
+                match FastFileRead::read(&mut ffr) {
+                    Ok(buf) if buf.is_empty() => break Ok(()),
+                    Ok(_) => continue,
+                    Err(e) => break Err(e),
+                }
+            };
+
+            asserting("Read fails").that(&res.is_err()).is_true();
+            let error = Error::from(res.err().unwrap()); // Safe, bc we checked above
+            asserting("Error kind")
+                .that(error.kind())
+                .is_equal_to(&ErrorKind::DigestMismatch {
+                    expected: wrong,
+                    actual:   expected(Algorithm::XxHash64, &fixture.contents()),
+                });
+        }
+
+        #[test]
+        fn fastfilereader_accepts_matching_digest() {
+            let fixture = testing::fixture(100_000, 4).expect("Failed to create test file");
+            let digest = expected(Algorithm::Sha256, &fixture.contents());
+
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .expect_digest(digest.clone())
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+            let mut content = Vec::new();
+            Read::read_to_end(&mut ffr, &mut content).expect("Failed to read file");
+
+            asserting("Digest").that(&ffr.digest()).is_equal_to(Some(&digest));
+        }
+    }
+
+    mod stats {
+        use super::*;
+
+        use crate::{backend::Advice, fastfile::FastFileRead};
+        use std::io::Read;
+
+        fn read_chunks(ffr: &mut FastFileReader) -> u64 {
+            let mut chunks = 0;
+            while !FastFileRead::read(ffr).expect("Failed to fastread file").is_empty() {
+                chunks += 1;
+            }
+            chunks
+        }
+
+        #[test]
+        fn stats_count_reads_of_file_backend() {
+            let size = 2 * MAX_READ_BUF_SIZE + 1;
+            let fixture = testing::fixture(size, 1).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let chunks = read_chunks(&mut ffr);
+            let stats = ffr.stats();
+
+            asserting("Bytes delivered")
+                .that(&stats.bytes_delivered)
+                .is_equal_to(size as u64);
+            asserting("Fast reads").that(&stats.fast_reads).is_equal_to(chunks + 1);
+            asserting("Backend reads")
+                .that(&stats.backend_reads)
+                .is_equal_to(chunks + 1);
+            asserting("Page faults").that(&stats.page_faults).is_none();
+        }
+
+        #[test]
+        fn stats_count_io_reads() {
+            let fixture = testing::fixture(100_000, 2).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let mut buf = [0u8; 1000];
+            let n = Read::read(&mut ffr, &mut buf).expect("Failed to read file");
+            let stats = ffr.stats();
+
+            asserting("Bytes delivered")
+                .that(&stats.bytes_delivered)
+                .is_equal_to(n as u64);
+            asserting("Fast reads").that(&stats.fast_reads).is_equal_to(0);
+            asserting("Backend reads").that(&stats.backend_reads).is_equal_to(1);
+        }
+
+        #[test]
+        fn stats_count_advise_calls() {
+            let fixture = testing::fixture(100_000, 3).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+
+            ffr.advise(Advice::Sequential).expect("Failed to advise");
+
+            asserting("Advise calls").that(&ffr.stats().advise_calls).is_equal_to(1);
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        #[test]
+        fn stats_count_page_faults_of_mmap_backend() {
+            let size = 2 * MAX_READ_BUF_SIZE + 1;
+            let fixture = testing::fixture(size, 4).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let before = ffr.stats().page_faults.expect("No page faults for mmap backend");
+            FastFileRead::read_to_end(&mut ffr).expect("Failed to read to end");
+            let after = ffr.stats().page_faults.expect("No page faults for mmap backend");
+
+            asserting("No pages read before")
+                .that(&(before.avoided + before.incurred))
+                .is_equal_to(0);
+            asserting("All pages read")
+                .that(&(after.avoided + after.incurred))
+                .is_equal_to(size.div_ceil(PAGE_SIZE) as u64);
+        }
+    }
+
+    mod report {
+        use super::*;
+
+        use crate::{
+            backend::{Advice, Backend},
+            decompress::Decompress,
+            errors::ErrorKind,
+            fastfile::FastFileRead,
+            strategy::{AdviceReport, StrategyReport},
+        };
+        use std::io::{self, Write};
+
+        #[test]
+        fn report_records_inputs_and_decisions() {
+            let fixture = testing::fixture(100_000, 1).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_size_hint(50_000)
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            asserting("Buffer not allocated before reading")
+                .that(&ffr.report().buffer_size)
+                .is_none();
+            FastFileRead::read(&mut ffr).expect("Failed to fastread file");
+            let report = ffr.report();
+
+            asserting("Strategy").that(&report.strategy).is_equal_to("file");
+            asserting("Size is taken from hint")
+                .that(&report.size)
+                .is_equal_to(50_000);
+            asserting("Size hint").that(&report.size_hint).is_equal_to(Some(50_000));
+            asserting("Backend").that(&report.backend.as_str()).is_equal_to("file");
+            asserting("Zero-copy").that(&report.zero_copy).is_false();
+            asserting("Buffer size")
+                .that(&report.buffer_size)
+                .is_equal_to(Some(optimal_buffer_size(50_000)));
+            asserting("Advice").that(&report.advice).is_empty();
+        }
+
+        #[test]
+        fn report_records_mmap_backend() {
+            let fixture = testing::fixture(100_000, 2).expect("Failed to create test file");
+            let ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+            let report = ffr.report();
+
+            asserting("Backend").that(&report.backend.as_str()).is_equal_to("mmap");
+            asserting("Zero-copy").that(&report.zero_copy).is_true();
+        }
+
+        #[test]
+        fn report_records_decompression() {
+            let fixture = testing::fixture(100_000, 3).expect("Failed to create test file");
+            let mut compressed = tempfile::NamedTempFile::new().expect("Failed to create test file");
+            let mut encoder = flate2::write::GzEncoder::new(compressed.as_file_mut(), flate2::Compression::default());
+            encoder.write_all(&fixture.contents()).expect("Failed to compress");
+            encoder.finish().expect("Failed to compress");
+
+            let ffr = FastFile::read(compressed.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .decompress(Decompress::Auto)
+                .open_with_strategy(&MmapStrategy)
+                .expect("Failed to open path as FastFile");
+            let report = ffr.report();
+
+            asserting("Backend")
+                .that(&report.backend.as_str())
+                .is_equal_to("Gzip decoder over mmap");
+            asserting("Zero-copy").that(&report.zero_copy).is_false();
+        }
+
+        #[test]
+        fn report_records_advice_and_results() {
+            struct FailingAdvice;
+            impl Backend for FailingAdvice {
+                fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> { Ok(0) }
+
+                fn size(&self) -> io::Result<u64> { Ok(0) }
+
+                fn advise(&mut self, advice: Advice) -> Result<()> {
+                    match advice {
+                        Advice::Sequential => Ok(()),
+                        Advice::WillNeed(_) => Err(ErrorKind::Unsupported("advice").into()),
+                    }
+                }
+            }
+            let mut ffr = FastFileReader::new(Box::new(FailingAdvice), 0);
+
+            ffr.advise(Advice::Sequential).expect("Failed to advise");
+            let res = ffr.advise(Advice::WillNeed(4096));
+
+            asserting("Advise fails").that(&res.is_err()).is_true();
+            asserting("Advice").that(&ffr.report().advice).is_equal_to(vec![
+                AdviceReport {
+                    advice: Advice::Sequential,
+                    error:  None,
+                },
+                AdviceReport {
+                    advice: Advice::WillNeed(4096),
+                    error:  Some("unsupported operation: advice".to_string()),
+                },
+            ]);
+        }
+
+        #[test]
+        fn report_displays_inputs_and_decisions() {
+            let mut report = StrategyReport::new("file".to_string(), false, 100_000);
+            report.fs_type = Some("apfs".to_string());
+            report.cache_residency = Some(0.25);
+            report.buffer_size = Some(65_536);
+            report.advice.push(AdviceReport {
+                advice: Advice::Sequential,
+                error:  None,
+            });
+
+            asserting("Display").that(&report.to_string()).is_equal_to(
+                "strategy: custom\ninputs: size 100000 bytes, fs type apfs, cache residency 25.0%\nbackend: \
+                 file\nbuffer size: 65536 bytes\nadvice: Sequential ok;"
+                    .to_string(),
+            );
+        }
+    }
+
+    mod adaptive_buffer {
+        use super::*;
+
+        use crate::fastfile::FastFileRead;
+
+        #[test]
+        fn fastfilereader_tunes_buffer_for_size_hint() {
+            let fixture = testing::fixture(1024 * 1024, 4).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_size_hint(PAGE_SIZE)
+                .with_adaptive_buffer()
+                .open()
+                .expect("Failed to open path as FastFile");
+
+            let mut contents = Vec::new();
+            let mut first_len = None;
+            loop {
+                let buf = ffr.read().expect("Failed to fastread file");
+                if buf.is_empty() {
+                    break;
+                }
+                first_len.get_or_insert(buf.len());
+                contents.extend_from_slice(buf);
+            }
+            let buffer_size = ffr.report().buffer_size.unwrap_or(0);
+
+            asserting("First chunk is sized by hint")
+                .that(&first_len)
+                .is_equal_to(Some(PAGE_SIZE));
+            asserting("Contents").that(&(contents == fixture.contents())).is_true();
+            asserting("Buffer size is page aligned")
+                .that(&(buffer_size % PAGE_SIZE))
+                .is_equal_to(0);
+            asserting("Buffer size is limited")
+                .that(&buffer_size)
+                .is_less_than_or_equal_to(MAX_READ_BUF_SIZE);
+        }
+
+        #[test]
+        fn fastfilereader_reads_full_chunks_of_tuned_size() {
+            let fixture = testing::fixture(64 * PAGE_SIZE, 5).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+            ffr.set_buffer_sizes(PAGE_SIZE, 4 * PAGE_SIZE);
+            ffr.set_adaptive_buffer(true);
+
+            let first = ffr.read_full().expect("Failed to fastread file").len();
+            let mut len = first;
+            loop {
+                let n = ffr.read_full().expect("Failed to fastread file").len();
+                if n == 0 {
+                    break;
+                }
+                asserting("Chunk size").that(&n).is_equal_to(first);
+                len += n;
+            }
+
+            asserting("Read bytes").that(&len).is_equal_to(fixture.size());
+        }
+
+        #[test]
+        fn fastfilereader_mixes_reads_and_full_reads_while_tuning() {
+            let fixture = testing::fixture(256 * PAGE_SIZE, 6).expect("Failed to create test file");
+            let mut ffr = FastFile::read(fixture.path())
+                .expect("Failed to create FastFileReaderBuilder")
+                .with_size_hint(PAGE_SIZE)
+                .with_adaptive_buffer()
+                .open_with_strategy(&testing::FileStrategy)
+                .expect("Failed to open path as FastFile");
+
+            let mut contents = Vec::new();
+            loop {
+                // Reads let the tuner grow the buffer, which full reads have to follow
+                for _ in 0..4 {
+                    contents.extend_from_slice(FastFileRead::read(&mut ffr).expect("Failed to fastread file"));
+                }
+                let buf = ffr.read_full().expect("Failed to fastread file");
+                if buf.is_empty() {
+                    break;
+                }
+                contents.extend_from_slice(buf);
+            }
+
+            asserting("Contents").that(&(contents == fixture.contents())).is_true();
+        }
+    }
+
+    mod memory_budget {
+        use super::*;
+
+        use crate::{backend, budget::MemoryBudget, fastfile::FastFileRead};
+        use std::fs::File;
+
+        fn budget(limit: usize) -> &'static MemoryBudget { Box::leak(Box::new(MemoryBudget::new(limit))) }
+
+        #[test]
+        fn fastfilereader_shrinks_buffer_to_budget() {
+            let fixture = testing::fixture(8 * PAGE_SIZE, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let budget = budget(2 * PAGE_SIZE);
+            let mut ffr = FastFile::read(&path)
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+            ffr.budget = budget;
+
+            let len = ffr.read().expect("Failed to fastread file").len();
+
+            asserting("Read size is limited by budget")
+                .that(&len)
+                .is_equal_to(2 * PAGE_SIZE);
+            asserting("Reserved")
+                .that(&budget.reserved())
+                .is_equal_to(2 * PAGE_SIZE);
+            drop(ffr);
+            asserting("Released on drop").that(&budget.reserved()).is_equal_to(0);
+        }
+
+        #[test]
+        fn fastfilereader_fails_if_budget_is_exhausted() {
+            let budget = budget(PAGE_SIZE - 1);
+            let mut ffr = FastFile::read("Cargo.toml")
+                .expect("Failed to create FastFileReaderBuilder")
+                .open_with_strategy(&FileStrategy)
+                .expect("Failed to open path as FastFile");
+            ffr.budget = budget;
+
+            let res = ffr.read();
+
+            asserting("Read fails").that(&res.is_err()).is_true();
+        }
+
+        #[test]
+        fn mmap_or_file_falls_back_to_file_if_budget_is_exhausted() {
+            let fixture = testing::fixture(2 * PAGE_SIZE, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let file = File::open(&path).expect("Failed to open test file");
+
+            let inner = backend::mmap_or_file_within(file, budget(PAGE_SIZE)).expect("Failed to create reader");
+
+            asserting("Falls back to file").that(&inner.is_zero_copy()).is_false();
+        }
+
+        #[test]
+        fn mmap_or_file_reserves_mapping() {
+            let fixture = testing::fixture(2 * PAGE_SIZE, 0).expect("Failed to create test file");
+            let path = fixture.path();
+            let file = File::open(&path).expect("Failed to open test file");
+            let budget = budget(4 * PAGE_SIZE);
+
+            let inner = backend::mmap_or_file_within(file, budget).expect("Failed to create reader");
+
+            asserting("Reserved")
+                .that(&budget.reserved())
+                .is_equal_to(2 * PAGE_SIZE);
+            drop(inner);
+            asserting("Released on drop").that(&budget.reserved()).is_equal_to(0);
+        }
+    }
+
     fn verify_reader<T: strategy::ReaderStrategy, F: Fn(&mut FastFileReader) -> (usize, Digest)>(
         reader_strategy: &T,
         reader: F,
@@ -392,7 +1906,8 @@ mod tests {
         let mut rng = SmallRng::from_entropy();
         let size = rng.gen_range(1024 * 1024 + 1, 2 * 1024 * 1024);
 
-        let path = fastfile_benches::utils::create_random_test_file(size).expect("Failed to create test file");
+        let fixture = testing::fixture(size, size as u64).expect("Failed to create test file");
+        let path = fixture.path();
         let mut ffr = FastFile::read(&path)
             .expect("Failed to create FastFileReaderBuilder")
             .open_with_strategy(reader_strategy)
@@ -402,34 +1917,11 @@ mod tests {
 
         assert_eq!(len, ffr.size(), "Read bytes differ from file size");
 
-        let expected_digest =
-            fastfile_benches::utils::get_digest_for_path(&path).expect("Failed to compute expected digest");
+        let expected_digest = ring::digest::digest(&SHA256, &fixture.contents());
         assert_eq!(
             digest.as_ref(),
             expected_digest.as_ref(),
             "Computed digest differes from expected digest"
         );
     }
-
-    struct TestFileReaderStragegy {}
-    impl strategy::ReaderStrategy for TestFileReaderStragegy {
-        fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
-            let FastFileReaderBuilder { file, .. } = ffrb;
-            let size = file.metadata().unwrap().len() as usize;
-            let inner = BackingReader::file(file)?;
-
-            Ok(FastFileReader::new(inner, size))
-        }
-    }
-
-    struct TestMmapReaderStragegy {}
-    impl strategy::ReaderStrategy for TestMmapReaderStragegy {
-        fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
-            let FastFileReaderBuilder { file, .. } = ffrb;
-            let size = file.metadata().unwrap().len() as usize;
-            let inner = BackingReader::mmap(file)?;
-
-            Ok(FastFileReader::new(inner, size))
-        }
-    }
 }
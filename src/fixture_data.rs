@@ -0,0 +1,32 @@
+/// xorshift64* generator; good enough for test data and stable across platforms and releases
+pub(crate) struct FixtureData {
+    state: u64,
+}
+
+impl FixtureData {
+    pub(crate) fn new(seed: u64) -> FixtureData {
+        // Scramble the seed with splitmix64, which maps distinct seeds to distinct states
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        // The state must not be zero
+        FixtureData {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub(crate) fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let word = self.next().to_le_bytes();
+            chunk.copy_from_slice(&word[..chunk.len()]);
+        }
+    }
+}
@@ -6,24 +6,58 @@
 
 #[deny(missing_docs)]
 
+/// Pluggable backends that FastFileReaders read from
+pub mod backend;
+
+/// Page aligned memory buffers for reading
+pub mod buffer;
+
+/// Process-wide memory budget for read buffers and memory mappings
+pub mod budget;
+
+/// Calibration of strategy profiles on the target machine
+#[cfg(feature = "calibration")]
+pub mod calibration;
+
+/// Transparent decompression of gzip and zstd files
+pub mod decompress;
+
+/// Checksums and hashes of the data read
+pub mod digest;
+
 /// Errors
 pub mod errors;
 
+/// Deterministic pseudo random data for fixture and calibration files
+#[cfg(any(test, feature = "testing", feature = "calibration"))]
+mod fixture_data;
+
 /// The `fastfile` module contains the FastFile type
 pub mod fastfile;
 
 /// Internal abstraction of OS specific function
 pub mod os;
 
+/// I/O statistics of FastFileReaders
+pub mod stats;
+
 /// OS specific file IO strategies
 pub mod strategy;
 
+/// In-memory and fault injecting backends and deterministic fixture files for tests
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// `prelude` for the most important types and functions
 pub mod prelude {
+    #[allow(deprecated)]
     pub use crate::{
+        budget::MemoryBudget,
+        buffer::{AlignedBuf, BufferPool},
+        decompress::Decompress,
         fastfile::{FastFile, MAX_READ_BUF_SIZE},
         prepare_buf,
     };
 }
 
-pub use crate::fastfile::FastFileRead;
+pub use crate::{digest::digest_file, fastfile::FastFileRead};
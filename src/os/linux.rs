@@ -0,0 +1,344 @@
+use crate::{
+    backend::Advice,
+    errors::*,
+    os::{DeviceInfo, FileSystemInfo, FileSystemKind},
+};
+
+use std::{
+    fs,
+    io,
+    mem,
+    os::unix::io::RawFd,
+    path::{Path, PathBuf},
+};
+
+/// File system magic numbers from `linux/magic.h` and the file systems' sources
+const FILESYSTEMS: &[(u32, &str, FileSystemKind)] = &[
+    (0xEF53, "ext4", FileSystemKind::Local),
+    (0x5846_5342, "xfs", FileSystemKind::Local),
+    (0x9123_683E, "btrfs", FileSystemKind::Local),
+    (0x2FC1_2FC1, "zfs", FileSystemKind::Local),
+    (0xF2F5_2010, "f2fs", FileSystemKind::Local),
+    (0x7371_7368, "squashfs", FileSystemKind::Local),
+    (0x4D44, "vfat", FileSystemKind::Local),
+    (0x2011_BAB0, "exfat", FileSystemKind::Local),
+    (0x5346_544E, "ntfs", FileSystemKind::Local),
+    (0x9660, "iso9660", FileSystemKind::Local),
+    (0x0102_1994, "tmpfs", FileSystemKind::Memory),
+    (0x8584_58F6, "ramfs", FileSystemKind::Memory),
+    (0x794C_7630, "overlayfs", FileSystemKind::Overlay),
+    (0x6969, "nfs", FileSystemKind::Network),
+    (0x517B, "smb", FileSystemKind::Network),
+    (0xFF53_4D42, "cifs", FileSystemKind::Network),
+    (0xFE53_4D42, "smb2", FileSystemKind::Network),
+    (0x0102_1997, "9p", FileSystemKind::Network),
+    (0x00C3_6400, "ceph", FileSystemKind::Network),
+    (0x5346_414F, "afs", FileSystemKind::Network),
+    (0x6573_5546, "fuse", FileSystemKind::Fuse),
+];
+
+/// Advises the kernel to read the first `file_size` bytes of `fd` into the page cache
+pub fn read_advise(fd: RawFd, file_size: usize) -> Result<()> {
+    let len = file_size.min(libc::off_t::MAX as usize) as libc::off_t;
+    fadvise(fd, len, libc::POSIX_FADV_WILLNEED)
+}
+
+/// Advises the kernel that `fd` is read sequentially, which doubles its read-ahead window
+pub fn read_ahead(fd: RawFd) -> Result<()> { fadvise(fd, 0, libc::POSIX_FADV_SEQUENTIAL) }
+
+/// Drops the pages of `fd` from the page cache; only pages that have been written back are dropped
+pub fn drop_page_cache(fd: RawFd) -> Result<()> { fadvise(fd, 0, libc::POSIX_FADV_DONTNEED) }
+
+fn fadvise(fd: RawFd, len: libc::off_t, advice: libc::c_int) -> Result<()> {
+    // `posix_fadvise` returns the error number instead of setting `errno`
+    let res = unsafe { libc::posix_fadvise(fd, 0, len, advice) };
+    if res != 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::with_source(
+                ErrorKind::LibcFailed("posix_fadvise"),
+                io::Error::from_raw_os_error(res),
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Passes on `advice` for the mapping at `mem` of `len` bytes
+pub fn advise_mapping(mem: *const u8, len: usize, advice: Advice) -> Result<()> {
+    let (len, advice) = match advice {
+        Advice::Sequential => (len, libc::MADV_SEQUENTIAL),
+        Advice::WillNeed(will_need) => (will_need.min(len), libc::MADV_WILLNEED),
+    };
+    let res = unsafe { libc::madvise(mem as *mut libc::c_void, len as libc::size_t, advice) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("madvise"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the type of the file system the file `fd` resides on
+pub fn filesystem_info(fd: RawFd) -> Result<FileSystemInfo> {
+    let mut stat: libc::statfs = unsafe { mem::zeroed() };
+    let res = unsafe { libc::fstatfs(fd, &mut stat) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fstatfs"),
+        ));
+    }
+
+    // `f_type` is signed on some architectures, but all magic numbers fit into 32 bits
+    Ok(filesystem_by_magic(stat.f_type as u32))
+}
+
+/// Returns the characteristics of the block device the file `fd` resides on
+///
+/// The device is looked up by `st_dev` in `/sys/dev/block`; partitions report the queue of their
+/// disk. Files without a block device, e.g. on tmpfs, get an unknown `DeviceInfo`.
+pub fn device_info(fd: RawFd) -> Result<DeviceInfo> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    let res = unsafe { libc::fstat(fd, &mut stat) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fstat"),
+        ));
+    }
+
+    Ok(device_info_in(
+        Path::new("/sys"),
+        libc::major(stat.st_dev),
+        libc::minor(stat.st_dev),
+    ))
+}
+
+fn device_info_in(sys: &Path, major: libc::c_uint, minor: libc::c_uint) -> DeviceInfo {
+    let device = match fs::canonicalize(sys.join(format!("dev/block/{}:{}", major, minor))) {
+        Ok(device) => device,
+        Err(_) => return DeviceInfo::default(),
+    };
+    let disk = match queue_dir(&device) {
+        Some(disk) => disk,
+        None => return DeviceInfo::default(),
+    };
+    let queue = disk.join("queue");
+
+    DeviceInfo {
+        name:                disk.file_name().map(|name| name.to_string_lossy().into_owned()),
+        rotational:          read_sys_value(&queue.join("rotational")).map(|rotational| rotational != 0),
+        logical_block_size:  read_sys_value(&queue.join("logical_block_size")),
+        physical_block_size: read_sys_value(&queue.join("physical_block_size")),
+        read_ahead_kb:       read_sys_value(&queue.join("read_ahead_kb")),
+        queue_depth:         read_sys_value(&queue.join("nr_requests")),
+    }
+}
+
+/// Returns the directory of the device that has the request queue; the disk for partitions
+fn queue_dir(device: &Path) -> Option<PathBuf> {
+    if device.join("queue").is_dir() {
+        return Some(device.to_path_buf());
+    }
+    let disk = device.parent()?;
+    if disk.join("queue").is_dir() {
+        Some(disk.to_path_buf())
+    } else {
+        None
+    }
+}
+
+fn read_sys_value(path: &Path) -> Option<usize> { fs::read_to_string(path).ok()?.trim().parse().ok() }
+
+fn filesystem_by_magic(magic: u32) -> FileSystemInfo {
+    match FILESYSTEMS.iter().find(|(m, ..)| *m == magic) {
+        Some((_, name, kind)) => {
+            FileSystemInfo {
+                kind: *kind,
+                name: name.to_string(),
+            }
+        }
+        None => {
+            FileSystemInfo {
+                kind: FileSystemKind::Unknown,
+                name: format!("{:#x}", magic),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    use std::{fs::File, os::unix::io::AsRawFd};
+
+    #[test]
+    fn test_read_advise() {
+        let f = get_file();
+        let file_size = f.metadata().expect("Could not get metadata of test file").len() as usize;
+
+        let res = read_advise(f.as_raw_fd(), file_size);
+
+        asserting("Read advise").that(&res.is_ok()).is_true();
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        let f = get_file();
+
+        let res = read_ahead(f.as_raw_fd());
+
+        asserting("Read ahead").that(&res.is_ok()).is_true();
+    }
+
+    #[test]
+    fn test_drop_page_cache() {
+        // Other tests expect Cargo.toml to be cached
+        let fixture = crate::testing::fixture(4096, 1).expect("Failed to create test file");
+        let f = fixture.open().expect("Could not open test file");
+
+        let res = drop_page_cache(f.as_raw_fd());
+
+        asserting("Drop page cache").that(&res.is_ok()).is_true();
+    }
+
+    #[test]
+    fn test_read_advise_invalid_fd() {
+        let res = read_advise(-1, 1024);
+
+        asserting("Read advise fails").that(&res.is_err()).is_true();
+    }
+
+    #[test]
+    fn test_advise_mapping() {
+        let f = get_file();
+        let mmap = unsafe { memmap::Mmap::map(&f).expect("Failed to map test file") };
+
+        for advice in &[Advice::Sequential, Advice::WillNeed(usize::MAX)] {
+            let res = advise_mapping(mmap.as_ptr(), mmap.len(), *advice);
+            asserting(&format!("Advise {:?}", advice)).that(&res.is_ok()).is_true();
+        }
+    }
+
+    #[test]
+    fn test_filesystem_info() {
+        let f = get_file();
+
+        let res = filesystem_info(f.as_raw_fd());
+
+        asserting("Get file system information").that(&res.is_ok()).is_true();
+        asserting("File system name")
+            .that(&res.unwrap().name().is_empty())
+            .is_false();
+    }
+
+    #[test]
+    fn test_filesystem_by_magic() {
+        asserting("tmpfs")
+            .that(&filesystem_by_magic(0x0102_1994).kind())
+            .is_equal_to(FileSystemKind::Memory);
+        asserting("NFS")
+            .that(&filesystem_by_magic(0x6969).kind())
+            .is_equal_to(FileSystemKind::Network);
+        asserting("FUSE")
+            .that(&filesystem_by_magic(0x6573_5546).kind())
+            .is_equal_to(FileSystemKind::Fuse);
+        let unknown = filesystem_by_magic(0x1234);
+        asserting("Unknown kind")
+            .that(&unknown.kind())
+            .is_equal_to(FileSystemKind::Unknown);
+        asserting("Unknown name").that(&unknown.name()).is_equal_to("0x1234");
+    }
+
+    fn fake_sys(sys: &Path, disk: &str, partition: Option<&str>, rotational: &str) {
+        let queue = sys.join("devices").join(disk).join("queue");
+        fs::create_dir_all(&queue).expect("Failed to create fake sysfs");
+        for (name, value) in &[
+            ("rotational", rotational),
+            ("logical_block_size", "512"),
+            ("physical_block_size", "4096"),
+            ("read_ahead_kb", "128"),
+            ("nr_requests", "64"),
+        ] {
+            fs::write(queue.join(name), format!("{}\n", value)).expect("Failed to write fake sysfs");
+        }
+        let device = match partition {
+            Some(partition) => {
+                let device = sys.join("devices").join(disk).join(partition);
+                fs::create_dir_all(&device).expect("Failed to create fake sysfs");
+                device
+            }
+            None => sys.join("devices").join(disk),
+        };
+        fs::create_dir_all(sys.join("dev/block")).expect("Failed to create fake sysfs");
+        std::os::unix::fs::symlink(device, sys.join("dev/block/8:1")).expect("Failed to link fake sysfs");
+    }
+
+    #[test]
+    fn test_device_info_of_partition() {
+        let sys = tempfile::tempdir().expect("Failed to create test directory");
+        fake_sys(sys.path(), "sda", Some("sda1"), "1");
+
+        let device = device_info_in(sys.path(), 8, 1);
+
+        asserting("Device info").that(&device).is_equal_to(DeviceInfo {
+            name:                Some("sda".to_string()),
+            rotational:          Some(true),
+            logical_block_size:  Some(512),
+            physical_block_size: Some(4096),
+            read_ahead_kb:       Some(128),
+            queue_depth:         Some(64),
+        });
+    }
+
+    #[test]
+    fn test_device_info_of_disk() {
+        let sys = tempfile::tempdir().expect("Failed to create test directory");
+        fake_sys(sys.path(), "nvme0n1", None, "0");
+
+        let device = device_info_in(sys.path(), 8, 1);
+
+        asserting("Name")
+            .that(&device.name)
+            .is_equal_to(Some("nvme0n1".to_string()));
+        asserting("Rotational")
+            .that(&device.rotational)
+            .is_equal_to(Some(false));
+    }
+
+    #[test]
+    fn test_device_info_without_block_device_is_unknown() {
+        let sys = tempfile::tempdir().expect("Failed to create test directory");
+
+        let device = device_info_in(sys.path(), 0, 42);
+
+        asserting("Device is unknown").that(&device.is_unknown()).is_true();
+    }
+
+    #[test]
+    fn test_device_info() {
+        let f = get_file();
+
+        let res = device_info(f.as_raw_fd());
+
+        asserting("Get device information").that(&res.is_ok()).is_true();
+    }
+
+    #[test]
+    fn test_filesystem_info_invalid_fd() {
+        let res = filesystem_info(-1);
+
+        asserting("Get file system information fails")
+            .that(&res.is_err())
+            .is_true();
+    }
+
+    fn get_file() -> File { File::open("Cargo.toml").expect("Could not open test file") }
+}
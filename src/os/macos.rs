@@ -1,11 +1,11 @@
 use crate::{
+    backend::Advice,
     errors::*,
-    os::{PageCacheInfo, PAGE_SIZE},
+    os::{FileSystemInfo, FileSystemKind},
 };
 
-use failure::Fail;
 use libc;
-use std::os::unix::io::RawFd;
+use std::{mem, os::unix::io::RawFd};
 
 #[allow(dead_code)]
 pub fn read_advise(fd: RawFd, file_size: usize) -> Result<()> {
@@ -17,8 +17,10 @@ pub fn read_advise(fd: RawFd, file_size: usize) -> Result<()> {
     };
     let res = unsafe { libc::fcntl(fd, libc::F_RDADVISE, &ra) };
     if res < 0 {
-        return Err(Error::from(ErrorKind::LibcFailed("fcntl F_RDADVISE")))
-            .map_err(|e| e.context(ErrorKind::FileOpFailed).into());
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fcntl F_RDADVISE"),
+        ));
     }
 
     Ok(())
@@ -28,57 +30,77 @@ pub fn read_advise(fd: RawFd, file_size: usize) -> Result<()> {
 pub fn read_ahead(fd: RawFd) -> Result<()> {
     let res = unsafe { libc::fcntl(fd, libc::F_RDAHEAD, 1) };
     if res < 0 {
-        return Err(Error::from(ErrorKind::LibcFailed("fcntl F_RDAHEAD")))
-            .map_err(|e| e.context(ErrorKind::FileOpFailed).into());
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fcntl F_RDAHEAD"),
+        ));
     }
 
     Ok(())
 }
 
-#[allow(dead_code)]
-pub fn get_page_cache_info(fd: RawFd, file_size: usize) -> Result<PageCacheInfo> {
-    let mem = unsafe {
-        let mem = libc::mmap(
-            std::ptr::null_mut(),
-            file_size as libc::size_t,
-            libc::PROT_READ,
-            libc::MAP_SHARED,
-            fd,
-            0,
-        );
-        if mem == libc::MAP_FAILED {
-            return Err(Error::from(ErrorKind::LibcFailed("mmap")))
-                .map_err(|e| e.context(ErrorKind::FileOpFailed).into());
-        }
-        mem
-    };
+/// Disables caching of the data read from `fd` in the page cache
+pub fn no_cache(fd: RawFd) -> Result<()> {
+    let res = unsafe { libc::fcntl(fd, libc::F_NOCACHE, 1) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fcntl F_NOCACHE"),
+        ));
+    }
+
+    Ok(())
+}
 
-    let num_pages = bytes_in_pages(file_size);
-    let mut pages: Vec<libc::c_char> = Vec::with_capacity(num_pages);
-    unsafe {
-        pages.set_len(num_pages);
+/// Passes on `advice` for the mapping at `mem` of `len` bytes
+pub fn advise_mapping(mem: *const u8, len: usize, advice: Advice) -> Result<()> {
+    let (len, advice) = match advice {
+        Advice::Sequential => (len, libc::MADV_SEQUENTIAL),
+        Advice::WillNeed(will_need) => (will_need.min(len), libc::MADV_WILLNEED),
+    };
+    let res = unsafe { libc::madvise(mem as *mut libc::c_void, len as libc::size_t, advice) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("madvise"),
+        ));
     }
 
-    let pages_array = pages.as_mut_slice();
-    unsafe {
-        let res = libc::mincore(mem, file_size as libc::size_t, pages_array.as_mut_ptr());
-        if res < 0 {
-            return Err(Error::from(ErrorKind::LibcFailed("mincore")))
-                .map_err(|e| e.context(ErrorKind::FileOpFailed).into());
-        }
+    Ok(())
+}
+
+/// Returns the type of the file system the file `fd` resides on
+pub fn filesystem_info(fd: RawFd) -> Result<FileSystemInfo> {
+    let mut stat: libc::statfs = unsafe { mem::zeroed() };
+    let res = unsafe { libc::fstatfs(fd, &mut stat) };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("fstatfs"),
+        ));
     }
-    let num_cached_pages = pages_array.iter().map(|x| (x & 0x1) as usize).sum();
+    let name: String = stat
+        .f_fstypename
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as u8 as char)
+        .collect();
+
+    Ok(filesystem_by_name(name))
+}
 
-    let pci = PageCacheInfo {
-        total:  num_pages,
-        cached: num_cached_pages,
+fn filesystem_by_name(name: String) -> FileSystemInfo {
+    let kind = match name.as_str() {
+        "apfs" | "hfs" | "msdos" | "exfat" | "ntfs" | "cd9660" | "udf" => FileSystemKind::Local,
+        "tmpfs" => FileSystemKind::Memory,
+        "nfs" | "smbfs" | "afpfs" | "webdav" | "cifs" => FileSystemKind::Network,
+        name if name.contains("fuse") => FileSystemKind::Fuse,
+        _ => FileSystemKind::Unknown,
     };
 
-    Ok(pci)
+    FileSystemInfo { kind, name }
 }
 
-fn bytes_in_pages(bytes: usize) -> usize { ((bytes + PAGE_SIZE - 1) / PAGE_SIZE) }
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -106,18 +128,42 @@ mod tests {
     }
 
     #[test]
-    fn test_get_page_cache_info() {
+    fn test_advise_mapping() {
         let f = get_file();
-        let file_size = f.metadata().expect("Could not get metadata of test file").len() as usize;
+        let mmap = unsafe { memmap::Mmap::map(&f).expect("Failed to map test file") };
 
-        let res = get_page_cache_info(f.as_raw_fd(), file_size);
-        asserting("Get page cache information").that(&res.is_ok()).is_true();
+        for advice in &[Advice::Sequential, Advice::WillNeed(usize::max_value())] {
+            let res = advise_mapping(mmap.as_ptr(), mmap.len(), *advice);
+            asserting(&format!("Advise {:?}", advice)).that(&res.is_ok()).is_true();
+        }
+    }
 
-        let pci = res.unwrap();
-        asserting("Number of pages").that(&pci.total()).is_equal_to(&1);
-        // Cargo.toml is always cached due to `cargo test` obviously reads it.
-        asserting("Number of cached pages").that(&pci.cached()).is_equal_to(&1);
-        asserting("Cached ratio").that(&pci.ratio()).is_equal_to(&1f32);
+    #[test]
+    fn test_filesystem_info() {
+        let f = get_file();
+
+        let res = filesystem_info(f.as_raw_fd());
+
+        asserting("Get file system information").that(&res.is_ok()).is_true();
+        asserting("File system name")
+            .that(&res.unwrap().name().is_empty())
+            .is_false();
+    }
+
+    #[test]
+    fn test_filesystem_by_name() {
+        asserting("apfs")
+            .that(&filesystem_by_name("apfs".to_string()).kind())
+            .is_equal_to(FileSystemKind::Local);
+        asserting("SMB")
+            .that(&filesystem_by_name("smbfs".to_string()).kind())
+            .is_equal_to(FileSystemKind::Network);
+        asserting("macFUSE")
+            .that(&filesystem_by_name("macfuse".to_string()).kind())
+            .is_equal_to(FileSystemKind::Fuse);
+        asserting("Unknown")
+            .that(&filesystem_by_name("lifs".to_string()).kind())
+            .is_equal_to(FileSystemKind::Unknown);
     }
 
     fn get_file() -> File { File::open("Cargo.toml").expect("Could not open test file") }
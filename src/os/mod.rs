@@ -1,13 +1,43 @@
+#[cfg(target_os = "linux")]
+mod linux;
 #[cfg(target_os = "macos")]
 mod macos;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod unix;
 
+#[cfg(target_os = "linux")]
+pub use linux::advise_mapping;
+#[cfg(target_os = "linux")]
+pub use linux::device_info;
+#[cfg(target_os = "linux")]
+pub use linux::drop_page_cache;
+#[cfg(target_os = "linux")]
+pub use linux::filesystem_info;
+#[cfg(target_os = "linux")]
+pub use linux::read_advise;
+#[cfg(target_os = "linux")]
+pub use linux::read_ahead;
+
+#[cfg(target_os = "macos")]
+pub use macos::advise_mapping;
+#[cfg(target_os = "macos")]
+pub use macos::filesystem_info;
 #[cfg(target_os = "macos")]
-pub use macos::get_page_cache_info;
+pub use macos::no_cache;
 #[cfg(target_os = "macos")]
 pub use macos::read_advise;
 #[cfg(target_os = "macos")]
 pub use macos::read_ahead;
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use unix::get_page_cache_info;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use unix::resident_pages;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub use unix::sample_page_cache_info;
+
+use std::fmt;
+
 // pub const PAGE_SIZE: usize = ???;
 include!(concat!(env!("OUT_DIR"), "/os_consts.rs"));
 
@@ -25,3 +55,101 @@ impl PageCacheInfo {
 
     pub fn ratio(&self) -> f32 { self.cached as f32 / self.total as f32 }
 }
+
+/// Class of a file system with regard to how files on it should be read
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileSystemKind {
+    /// File system on a local block device, e.g. ext4, xfs, btrfs, or apfs
+    Local,
+    /// File system in memory, e.g. tmpfs; reads never wait for a device
+    Memory,
+    /// Union of other file systems, e.g. overlayfs in containers
+    Overlay,
+    /// File system on a remote host, e.g. NFS, SMB, or 9p; mappings may fault on network errors
+    Network,
+    /// File system in user space; every page fault is a round trip to a process
+    Fuse,
+    /// File system that is not known to `fastfile`
+    Unknown,
+}
+
+/// Type of the file system a file resides on
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileSystemInfo {
+    kind: FileSystemKind,
+    name: String,
+}
+
+impl FileSystemInfo {
+    /// Creates the description of a file system, e.g. to plan reads for a hypothetical one
+    pub fn new<S: Into<String>>(kind: FileSystemKind, name: S) -> FileSystemInfo {
+        FileSystemInfo {
+            kind,
+            name: name.into(),
+        }
+    }
+
+    pub fn kind(&self) -> FileSystemKind { self.kind }
+
+    pub fn name(&self) -> &str { &self.name }
+}
+
+/// Characteristics of the block device a file resides on
+///
+/// Fields are `None` if they are unknown, e.g. for files on tmpfs, overlayfs, or btrfs, which
+/// have no single block device, and on operating systems other than Linux.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeviceInfo {
+    /// Name of the block device, e.g. `nvme0n1` or `sda`
+    pub name:                Option<String>,
+    /// Whether the device has rotating disks
+    pub rotational:          Option<bool>,
+    /// Smallest unit the device can address in bytes
+    pub logical_block_size:  Option<usize>,
+    /// Smallest unit the device can write without read-modify-write in bytes
+    pub physical_block_size: Option<usize>,
+    /// Read-ahead window of the kernel for the device in KiB
+    pub read_ahead_kb:       Option<usize>,
+    /// Number of requests the device queue holds
+    pub queue_depth:         Option<usize>,
+}
+
+impl DeviceInfo {
+    /// Returns whether nothing is known about the device
+    pub fn is_unknown(&self) -> bool { *self == DeviceInfo::default() }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "{}", name)?,
+            None => write!(f, "unknown")?,
+        }
+        match self.rotational {
+            Some(true) => write!(f, " (hdd")?,
+            Some(false) => write!(f, " (ssd")?,
+            None => write!(f, " (unknown type")?,
+        }
+        if let Some(read_ahead_kb) = self.read_ahead_kb {
+            write!(f, ", read ahead {} KiB", read_ahead_kb)?;
+        }
+        if let Some(queue_depth) = self.queue_depth {
+            write!(f, ", queue depth {}", queue_depth)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Returns unknown characteristics, because only Linux exposes them via sysfs
+#[cfg(not(target_os = "linux"))]
+pub fn device_info(_fd: std::os::unix::io::RawFd) -> crate::errors::Result<DeviceInfo> { Ok(DeviceInfo::default()) }
+
+/// Fails, because the residency of pages is only determined on Linux and macOS
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn sample_page_cache_info(
+    _fd: std::os::unix::io::RawFd,
+    _file_size: usize,
+    _max_pages: usize,
+) -> crate::errors::Result<PageCacheInfo> {
+    Err(crate::errors::ErrorKind::Unsupported("page cache residency").into())
+}
@@ -0,0 +1,161 @@
+use crate::{
+    errors::*,
+    os::{PageCacheInfo, PAGE_SIZE},
+};
+
+use std::os::unix::io::RawFd;
+
+/// Number of windows `sample_page_cache_info` spreads its samples over
+const SAMPLE_WINDOWS: usize = 64;
+
+#[allow(dead_code)]
+pub fn get_page_cache_info(fd: RawFd, file_size: usize) -> Result<PageCacheInfo> {
+    sample_page_cache_info(fd, file_size, usize::MAX)
+}
+
+/// Like `get_page_cache_info`, but checks at most `max_pages` pages spread evenly over the file
+///
+/// The ratio of the sample estimates the ratio of the file; `total` and `cached` count sampled
+/// pages only.
+pub fn sample_page_cache_info(fd: RawFd, file_size: usize, max_pages: usize) -> Result<PageCacheInfo> {
+    // Empty files cannot be mapped
+    if file_size == 0 {
+        return Ok(PageCacheInfo { total: 0, cached: 0 });
+    }
+
+    let mem = unsafe {
+        let mem = libc::mmap(
+            std::ptr::null_mut(),
+            file_size as libc::size_t,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            fd,
+            0,
+        );
+        if mem == libc::MAP_FAILED {
+            return Err(Error::with_source(
+                ErrorKind::FileOpFailed,
+                Error::last_os_error("mmap"),
+            ));
+        }
+        mem
+    };
+
+    let res = sample_resident_pages(mem as *const u8, file_size, max_pages);
+    unsafe { libc::munmap(mem, file_size as libc::size_t) };
+    let (total, cached) = res?;
+
+    let pci = PageCacheInfo { total, cached };
+
+    Ok(pci)
+}
+
+/// Returns the number of sampled and of resident pages of the mapping at `mem` of `len` bytes
+fn sample_resident_pages(mem: *const u8, len: usize, max_pages: usize) -> Result<(usize, usize)> {
+    let num_pages = len.div_ceil(PAGE_SIZE);
+    let windows = if num_pages <= max_pages {
+        1
+    } else {
+        SAMPLE_WINDOWS.min(max_pages).max(1)
+    };
+    let window_len = (num_pages.min(max_pages) / windows).max(1) * PAGE_SIZE;
+    let stride = num_pages / windows * PAGE_SIZE;
+
+    let mut total = 0;
+    let mut cached = 0;
+    for i in 0..windows {
+        let offset = i * stride;
+        let pages = resident_pages(mem.wrapping_add(offset), window_len.min(len - offset))?;
+        total += pages.len();
+        cached += pages.iter().filter(|resident| **resident).count();
+    }
+
+    Ok((total, cached))
+}
+
+/// Returns for every page of the mapping at `mem` of `len` bytes whether it is resident in memory
+pub fn resident_pages(mem: *const u8, len: usize) -> Result<Vec<bool>> {
+    let num_pages = len.div_ceil(PAGE_SIZE);
+    // `mincore` takes `char` on macOS and `unsigned char` on Linux
+    let mut pages: Vec<u8> = vec![0; num_pages];
+    let res = unsafe {
+        libc::mincore(
+            mem as *mut libc::c_void,
+            len as libc::size_t,
+            pages.as_mut_ptr() as *mut _,
+        )
+    };
+    if res < 0 {
+        return Err(Error::with_source(
+            ErrorKind::FileOpFailed,
+            Error::last_os_error("mincore"),
+        ));
+    }
+
+    Ok(pages.iter().map(|x| x & 0x1 == 1).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    use std::{fs::File, os::unix::io::AsRawFd};
+
+    #[test]
+    fn test_get_page_cache_info() {
+        let f = get_file();
+        let file_size = f.metadata().expect("Could not get metadata of test file").len() as usize;
+
+        let res = get_page_cache_info(f.as_raw_fd(), file_size);
+        asserting("Get page cache information").that(&res.is_ok()).is_true();
+
+        let pci = res.unwrap();
+        asserting("Number of pages").that(&pci.total()).is_equal_to(&1);
+        // Cargo.toml is always cached due to `cargo test` obviously reads it.
+        asserting("Number of cached pages").that(&pci.cached()).is_equal_to(&1);
+        asserting("Cached ratio").that(&pci.ratio()).is_equal_to(&1f32);
+    }
+
+    #[test]
+    fn test_sample_page_cache_info() {
+        let fixture = crate::testing::fixture(1000 * PAGE_SIZE + 1, 1).expect("Failed to create test file");
+        let f = fixture.open().expect("Could not open test file");
+
+        let pci = sample_page_cache_info(f.as_raw_fd(), fixture.size(), 128).expect("Failed to sample page cache");
+
+        asserting("Number of sampled pages")
+            .that(&pci.total())
+            .is_equal_to(&(SAMPLE_WINDOWS * 2));
+        // The test file has just been written
+        asserting("Cached ratio").that(&pci.ratio()).is_equal_to(&1f32);
+    }
+
+    #[test]
+    fn test_sample_page_cache_info_of_small_file_checks_all_pages() {
+        let fixture = crate::testing::fixture(3 * PAGE_SIZE, 1).expect("Failed to create test file");
+        let f = fixture.open().expect("Could not open test file");
+
+        let pci = sample_page_cache_info(f.as_raw_fd(), fixture.size(), 128).expect("Failed to sample page cache");
+
+        asserting("Number of pages").that(&pci.total()).is_equal_to(&3);
+    }
+
+    #[test]
+    fn test_resident_pages() {
+        let fixture = crate::testing::fixture(3 * PAGE_SIZE + 1, 1).expect("Failed to create test file");
+        let f = fixture.open().expect("Could not open test file");
+        let mmap = unsafe { memmap::Mmap::map(&f).expect("Failed to map test file") };
+
+        let pages = resident_pages(mmap.as_ptr(), mmap.len()).expect("Failed to get resident pages");
+
+        asserting("Number of pages").that(&pages.len()).is_equal_to(4);
+        // The test file has just been written
+        asserting("All pages are resident")
+            .that(&pages.iter().all(|resident| *resident))
+            .is_true();
+    }
+
+    fn get_file() -> File { File::open("Cargo.toml").expect("Could not open test file") }
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+/// `ReadStats` describes what a `FastFileReader` actually did
+///
+/// Returned by `FastFileReader::stats`; all counters start at zero when the reader is created.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReadStats {
+    /// Bytes delivered to the caller, including zeros filled in for failed reads
+    pub bytes_delivered: u64,
+    /// Read calls issued to the backend, i.e. `read` system calls for file backends
+    pub backend_reads:   u64,
+    /// Calls of `FastFileRead::read`, `FastFileRead::read_full`, and `FastFileRead::read_to_end`
+    pub fast_reads:      u64,
+    /// Advice about the access pattern passed on to the backend
+    pub advise_calls:    u64,
+    /// Page faults of memory mapped backends for the pages read so far; `None` for backends that
+    /// do not map the file
+    pub page_faults:     Option<PageFaults>,
+    /// Time spent blocked in backend reads and advice
+    pub io_wait:         Duration,
+}
+
+/// `PageFaults` counts the pages of a memory mapping that have been read so far
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PageFaults {
+    /// Pages that were in the page cache before the first read
+    pub avoided:  u64,
+    /// Pages that were not in the page cache before the first read and had to be faulted in
+    pub incurred: u64,
+}
@@ -0,0 +1,236 @@
+use crate::{
+    backend::Advice,
+    errors::*,
+    fastfile::{FastFileReader, FastFileReaderBuilder},
+    os,
+    strategy::{
+        advice_depends_on_device,
+        read_ahead_advice,
+        FileFacts,
+        PlannedBackend,
+        ReadPlan,
+        ReaderStrategy,
+        StrategyConfig,
+        SystemFacts,
+    },
+};
+
+use std::os::unix::io::AsRawFd;
+
+/// Pages checked at most for the residency of a file; larger files are sampled
+const DEFAULT_SAMPLE_PAGES: usize = 1024;
+
+/// `CacheAwareReaderStrategy` chooses the backend by how much of the file is in the page cache
+///
+/// Files with a residency of at least the hot threshold are memory mapped, because reading them
+/// does not need any I/O. Files with a residency of at most the cold threshold are streamed with
+/// read-ahead according to the `StrategyConfig`. Partially cached files are memory mapped and the
+/// whole file is advised with `Advice::WillNeed`; the residency is only sampled, so it does not
+/// tell which pages are missing, and the OS skips the pages that are cached already. The measured
+/// residency is recorded as `StrategyReport::cache_residency`. The residency is measured on Linux
+/// and macOS; elsewhere every file is treated as cold.
+pub struct CacheAwareReaderStrategy {
+    config:         StrategyConfig,
+    cold_threshold: f32,
+    hot_threshold:  f32,
+    sample_pages:   usize,
+}
+
+impl Default for CacheAwareReaderStrategy {
+    fn default() -> CacheAwareReaderStrategy { CacheAwareReaderStrategy::with_config(StrategyConfig::default()) }
+}
+
+impl CacheAwareReaderStrategy {
+    /// Creates the strategy with the thresholds of `config` for streamed files
+    pub fn with_config(config: StrategyConfig) -> CacheAwareReaderStrategy {
+        CacheAwareReaderStrategy {
+            config,
+            cold_threshold: 0.1,
+            hot_threshold: 1.0,
+            sample_pages: DEFAULT_SAMPLE_PAGES,
+        }
+    }
+
+    /// Sets the residency ratios up to which a file is cold and from which it is hot; default to
+    /// 0.1 and 1.0
+    pub fn with_residency_thresholds(self, cold: f32, hot: f32) -> CacheAwareReaderStrategy {
+        CacheAwareReaderStrategy {
+            cold_threshold: cold,
+            hot_threshold: hot,
+            ..self
+        }
+    }
+
+    /// Sets the number of pages checked at most for the residency of a file; defaults to 1024
+    pub fn with_sample_pages(self, sample_pages: usize) -> CacheAwareReaderStrategy {
+        CacheAwareReaderStrategy { sample_pages, ..self }
+    }
+
+    /// Returns the thresholds for streamed files
+    pub fn config(&self) -> &StrategyConfig { &self.config }
+
+    /// Plans how to read a file described by `file` on a system described by `system`
+    ///
+    /// Files of unknown residency are treated as cold.
+    pub fn plan(&self, file: &FileFacts, system: &SystemFacts) -> ReadPlan {
+        let (backend, advice) = match file.cache_residency {
+            Some(ratio) if ratio >= self.hot_threshold && system.can_map(file.size) => (PlannedBackend::Mmap, None),
+            Some(ratio) if ratio > self.cold_threshold && system.can_map(file.size) => {
+                (PlannedBackend::Mmap, Some(Advice::WillNeed(file.size)))
+            }
+            _ => {
+                (
+                    PlannedBackend::File,
+                    read_ahead_advice(file.size, &file.device, &self.config),
+                )
+            }
+        };
+
+        ReadPlan {
+            backend,
+            advice: advice.into_iter().collect(),
+            min_buffer_size: self.config.min_buffer_size,
+            max_buffer_size: self.config.max_buffer_size,
+            adaptive_buffer: self.config.adaptive_buffer,
+        }
+    }
+
+    fn residency(&self, ffrb: &FastFileReaderBuilder) -> Option<f32> {
+        let len = ffrb.file.metadata().ok()?.len() as usize;
+        // Files that cannot be mapped, e.g. pipes, are treated as cold
+        let pci = os::sample_page_cache_info(ffrb.file.as_raw_fd(), len, self.sample_pages).ok()?;
+        if pci.total() == 0 {
+            return None;
+        }
+
+        Some(pci.ratio())
+    }
+}
+
+impl ReaderStrategy for CacheAwareReaderStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        self.config.validate()?;
+        if self.cold_threshold > self.hot_threshold {
+            return Err(ErrorKind::InvalidConfig("cold threshold exceeds hot threshold").into());
+        }
+        let mut facts = FileFacts::gather(&ffrb)?;
+        if advice_depends_on_device(facts.size, &self.config) {
+            facts.gather_device(&ffrb);
+        }
+        facts.cache_residency = self.residency(&ffrb);
+        let plan = self.plan(&facts, &SystemFacts::gather());
+
+        plan.execute(ffrb.file, &facts)
+    }
+
+    fn name(&self) -> &'static str { "cache aware" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testing;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn cached_file_is_memory_mapped() {
+        // The test file has just been written, so it is in the page cache
+        let ffr = testing::open_fixture(&CacheAwareReaderStrategy::default(), 100_000).expect("Failed to open file");
+        let report = ffr.report();
+
+        asserting("Strategy").that(&report.strategy).is_equal_to("cache aware");
+        asserting("Cache residency")
+            .that(&report.cache_residency)
+            .is_equal_to(Some(1.0));
+        asserting("Backend").that(&report.backend.as_str()).is_equal_to("mmap");
+        asserting("No advice").that(&report.advice).is_empty();
+    }
+
+    #[test]
+    fn partially_cached_file_is_prefetched() {
+        let strategy = CacheAwareReaderStrategy::default().with_residency_thresholds(0.0, 2.0);
+
+        let ffr = testing::open_fixture(&strategy, 100_000).expect("Failed to open file");
+        let report = ffr.report();
+
+        asserting("Backend").that(&report.backend.as_str()).is_equal_to("mmap");
+        asserting("Advice")
+            .that(&report.advice.iter().map(|advice| advice.advice).collect::<Vec<_>>())
+            .is_equal_to(vec![Advice::WillNeed(100_000)]);
+        asserting("Advice succeeded").that(&report.advice[0].error).is_none();
+    }
+
+    #[test]
+    fn cold_file_is_streamed() {
+        let strategy = CacheAwareReaderStrategy::default().with_residency_thresholds(1.0, 2.0);
+
+        let ffr = testing::open_fixture(&strategy, 1000).expect("Failed to open file");
+
+        asserting("Backend")
+            .that(&ffr.report().backend.as_str())
+            .is_equal_to("file");
+        asserting("Cache residency")
+            .that(&ffr.report().cache_residency)
+            .is_equal_to(Some(1.0));
+    }
+
+    #[test]
+    fn invalid_thresholds_fail() {
+        let strategy = CacheAwareReaderStrategy::default().with_residency_thresholds(0.5, 0.1);
+
+        let res = testing::open_fixture(&strategy, 1000);
+
+        asserting("Open fails").that(&res.is_err()).is_true();
+    }
+
+    fn facts(cache_residency: Option<f32>) -> FileFacts {
+        FileFacts {
+            size: 100_000,
+            cache_residency,
+            ..FileFacts::default()
+        }
+    }
+
+    #[test]
+    fn plan_chooses_backend_by_residency() {
+        let strategy = CacheAwareReaderStrategy::default();
+        let system = SystemFacts::default();
+
+        let hot = strategy.plan(&facts(Some(1.0)), &system);
+        let partial = strategy.plan(&facts(Some(0.5)), &system);
+        let cold = strategy.plan(&facts(Some(0.05)), &system);
+        let unknown = strategy.plan(&facts(None), &system);
+
+        asserting("Hot backend")
+            .that(&hot.backend)
+            .is_equal_to(PlannedBackend::Mmap);
+        asserting("Hot advice").that(&hot.advice).is_empty();
+        asserting("Partial backend")
+            .that(&partial.backend)
+            .is_equal_to(PlannedBackend::Mmap);
+        asserting("Partial advice")
+            .that(&partial.advice)
+            .is_equal_to(vec![Advice::WillNeed(100_000)]);
+        asserting("Cold backend")
+            .that(&cold.backend)
+            .is_equal_to(PlannedBackend::File);
+        asserting("Unknown backend")
+            .that(&unknown.backend)
+            .is_equal_to(PlannedBackend::File);
+    }
+
+    #[test]
+    fn plan_streams_hot_files_beyond_memory_budget() {
+        let system = SystemFacts {
+            memory_available: Some(1000),
+        };
+
+        let plan = CacheAwareReaderStrategy::default().plan(&facts(Some(1.0)), &system);
+
+        asserting("Backend")
+            .that(&plan.backend)
+            .is_equal_to(PlannedBackend::File);
+    }
+}
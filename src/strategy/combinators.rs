@@ -0,0 +1,270 @@
+use crate::{
+    backend::Advice,
+    errors::*,
+    fastfile::{FastFileReader, FastFileReaderBuilder},
+    strategy::{get_file_size, ReaderStrategy, UNNAMED},
+};
+
+use std::io::{Seek, SeekFrom};
+
+/// Records `strategy` as the strategy that opened `reader` unless a nested strategy has done so
+fn record_strategy<T: ReaderStrategy + ?Sized>(reader: &mut FastFileReader, strategy: &T) {
+    if reader.report().strategy == UNNAMED {
+        reader.report_mut().strategy = strategy.name();
+    }
+}
+
+/// `SizeSwitch` delegates to different strategies depending on the size of the file
+///
+/// A file is opened by the strategy with the smallest limit above its size and by the `otherwise`
+/// strategy if there is none. The size is determined by `get_file_size`.
+pub struct SizeSwitch {
+    below:     Vec<(usize, Box<dyn ReaderStrategy>)>,
+    otherwise: Box<dyn ReaderStrategy>,
+}
+
+impl SizeSwitch {
+    /// Creates a switch that opens all files with `otherwise`
+    pub fn new<T: ReaderStrategy + 'static>(otherwise: T) -> SizeSwitch {
+        SizeSwitch {
+            below:     Vec::new(),
+            otherwise: Box::new(otherwise),
+        }
+    }
+
+    /// Opens files smaller than `limit` bytes with `strategy`
+    pub fn below<T: ReaderStrategy + 'static>(mut self, limit: usize, strategy: T) -> SizeSwitch {
+        let index = self
+            .below
+            .iter()
+            .position(|(other, _)| *other > limit)
+            .unwrap_or(self.below.len());
+        self.below.insert(index, (limit, Box::new(strategy)));
+        self
+    }
+
+    fn strategy(&self, size: usize) -> &dyn ReaderStrategy {
+        self.below
+            .iter()
+            .find(|(limit, _)| size < *limit)
+            .map(|(_, strategy)| strategy.as_ref())
+            .unwrap_or_else(|| self.otherwise.as_ref())
+    }
+}
+
+impl ReaderStrategy for SizeSwitch {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let strategy = self.strategy(get_file_size(&ffrb)?);
+        let mut reader = strategy.get_reader(ffrb)?;
+        record_strategy(&mut reader, strategy);
+
+        Ok(reader)
+    }
+
+    fn name(&self) -> &'static str { "size switch" }
+}
+
+/// `Fallback` opens files with the first strategy and with the second one if the first fails
+///
+/// For example, `Fallback(mmap, file)` reads files that cannot be memory mapped via read system
+/// calls. The failure of the first strategy is recorded in the `StrategyReport` as a warning.
+///
+/// The first strategy consumes the file, so the second one gets the file opened again by its path
+/// at the same offset. Only files without a path are duplicated before the first strategy tries.
+pub struct Fallback<A, B>(pub A, pub B);
+
+impl<A: ReaderStrategy, B: ReaderStrategy> ReaderStrategy for Fallback<A, B> {
+    fn get_reader(&self, mut ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let settings = ffrb.settings();
+        let duplicate = match ffrb.path {
+            Some(_) => None,
+            None => Some(ffrb.try_clone()?),
+        };
+        // The duplicate shares the file offset, which the first strategy may move, and a reopened
+        // file starts at the beginning
+        let position = ffrb
+            .file
+            .stream_position()
+            .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?;
+
+        match self.0.get_reader(ffrb) {
+            Ok(mut reader) => {
+                record_strategy(&mut reader, &self.0);
+                Ok(reader)
+            }
+            Err(e) => {
+                let mut fallback = match duplicate {
+                    Some(duplicate) => duplicate,
+                    None => settings.reopen()?,
+                };
+                fallback
+                    .file
+                    .seek(SeekFrom::Start(position))
+                    .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?;
+                let mut reader = self.1.get_reader(fallback)?;
+                record_strategy(&mut reader, &self.1);
+                let warning = format!("{} strategy failed: {}", self.0.name(), e);
+                reader.report_mut().warnings.push(warning);
+                Ok(reader)
+            }
+        }
+    }
+
+    fn name(&self) -> &'static str { "fallback" }
+}
+
+/// `WithAdvice` opens files with the inner strategy and passes on additional advice
+///
+/// Failing advice fails opening the file; combine with `Fallback` to ignore it.
+pub struct WithAdvice<S>(pub S, pub Advice);
+
+impl<S: ReaderStrategy> ReaderStrategy for WithAdvice<S> {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let mut reader = self.0.get_reader(ffrb)?;
+        record_strategy(&mut reader, &self.0);
+        reader.advise(self.1)?;
+
+        Ok(reader)
+    }
+
+    fn name(&self) -> &'static str { "with advice" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        fastfile::FastFile,
+        testing::{self, FileStrategy, MmapStrategy},
+        FastFileRead,
+    };
+
+    use spectral::prelude::*;
+
+    struct FailingStrategy;
+    impl ReaderStrategy for FailingStrategy {
+        fn get_reader(&self, mut ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+            ffrb.file
+                .seek(SeekFrom::Start(10))
+                .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?;
+            Err(ErrorKind::Unsupported("failing strategy").into())
+        }
+
+        fn name(&self) -> &'static str { "failing" }
+    }
+
+    fn open<T: ReaderStrategy>(strategy: &T, size: usize) -> FastFileReader {
+        testing::open_fixture(strategy, size).expect("Failed to open path as FastFile")
+    }
+
+    #[test]
+    fn size_switch_chooses_strategy_by_size() {
+        let strategy = SizeSwitch::new(MmapStrategy)
+            .below(64 * 1024, FileStrategy)
+            .below(16, FailingStrategy);
+
+        let tiny = testing::open_fixture(&strategy, 10);
+        let small = open(&strategy, 1000);
+        let large = open(&strategy, 100_000);
+
+        asserting("Tiny file strategy fails")
+            .that(&tiny.err().map(|e| e.kind().clone()))
+            .is_equal_to(Some(ErrorKind::Unsupported("failing strategy")));
+        asserting("Small file strategy")
+            .that(&small.report().strategy)
+            .is_equal_to("file");
+        asserting("Large file strategy")
+            .that(&large.report().strategy)
+            .is_equal_to("mmap");
+        asserting("Large file backend")
+            .that(&large.report().backend.as_str())
+            .is_equal_to("mmap");
+    }
+
+    #[test]
+    fn fallback_uses_second_strategy_on_error() {
+        let fixture = testing::fixture(1000, 2).expect("Failed to create test file");
+        let mut ffr = FastFile::read(fixture.path())
+            .expect("Failed to create FastFileReaderBuilder")
+            .open_with_strategy(&Fallback(FailingStrategy, FileStrategy))
+            .expect("Failed to open path as FastFile");
+
+        let contents = FastFileRead::read_to_end(&mut ffr)
+            .expect("Failed to fastread file")
+            .to_vec();
+
+        asserting("Strategy").that(&ffr.report().strategy).is_equal_to("file");
+        asserting("Warning")
+            .that(&ffr.report().warnings)
+            .is_equal_to(vec![
+                "failing strategy failed: unsupported operation: failing strategy".to_string()
+            ]);
+        asserting("Reads from the start")
+            .that(&(contents == fixture.contents()))
+            .is_true();
+    }
+
+    #[test]
+    fn fallback_duplicates_file_without_path() {
+        let fixture = testing::fixture(1000, 3).expect("Failed to create test file");
+        let mut ffrb = FastFile::read(fixture.path()).expect("Failed to create FastFileReaderBuilder");
+        ffrb.path = None;
+
+        let mut ffr = ffrb
+            .open_with_strategy(&Fallback(FailingStrategy, FileStrategy))
+            .expect("Failed to open path as FastFile");
+        let contents = FastFileRead::read_to_end(&mut ffr)
+            .expect("Failed to fastread file")
+            .to_vec();
+
+        asserting("Strategy").that(&ffr.report().strategy).is_equal_to("file");
+        asserting("Reads from the start")
+            .that(&(contents == fixture.contents()))
+            .is_true();
+    }
+
+    #[test]
+    fn fallback_keeps_first_strategy_on_success() {
+        let ffr = open(&Fallback(MmapStrategy, FileStrategy), 1000);
+
+        asserting("Strategy").that(&ffr.report().strategy).is_equal_to("mmap");
+        asserting("No warnings").that(&ffr.report().warnings).is_empty();
+    }
+
+    #[test]
+    fn with_advice_passes_on_advice() {
+        let strategy = WithAdvice(SizeSwitch::new(MmapStrategy), Advice::Sequential);
+
+        let ffr = open(&strategy, 1000);
+
+        asserting("Nested strategy")
+            .that(&ffr.report().strategy)
+            .is_equal_to("mmap");
+        asserting("Advice")
+            .that(
+                &ffr.report()
+                    .advice
+                    .iter()
+                    .map(|advice| advice.advice)
+                    .collect::<Vec<_>>(),
+            )
+            .is_equal_to(vec![Advice::Sequential]);
+    }
+
+    #[test]
+    fn unnamed_strategies_are_named_by_combinator() {
+        struct Unnamed;
+        impl ReaderStrategy for Unnamed {
+            fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+                FileStrategy.get_reader(ffrb)
+            }
+        }
+
+        let ffr = open(&Fallback(Unnamed, FileStrategy), 1000);
+
+        asserting("Strategy")
+            .that(&ffr.report().strategy)
+            .is_equal_to("fallback");
+    }
+}
@@ -0,0 +1,101 @@
+use crate::{
+    errors::*,
+    fastfile::{MAX_READ_BUF_SIZE, MIN_READ_BUF_SIZE},
+    os,
+};
+
+/// `StrategyConfig` holds the thresholds the default strategies base their decisions on
+///
+/// `StrategyConfig::default()` holds the values the strategies have been tuned with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct StrategyConfig {
+    /// Files of at least this size get advice to read ahead
+    pub read_ahead_threshold: usize,
+    /// Files larger than this size are advised to be read entirely instead of read ahead
+    pub advise_threshold:     usize,
+    /// Files of at least this size are memory mapped if the `MemoryBudget` allows it; `None`
+    /// never maps files
+    pub mmap_threshold:       Option<usize>,
+    /// Minimal size of read buffers; must be a multiple of the page size
+    pub min_buffer_size:      usize,
+    /// Maximal size of read buffers; must be a multiple of the page size
+    pub max_buffer_size:      usize,
+    /// Read files that are not memory mapped without caching them in the page cache
+    pub direct_io:            bool,
+    /// Tune the read buffer size by measuring the throughput of the first reads; see
+    /// `FastFileReader::set_adaptive_buffer`
+    pub adaptive_buffer:      bool,
+    /// Decide by the file system type: never memory map files on network and FUSE file systems,
+    /// always memory map files on tmpfs, and skip advice there
+    pub filesystem_aware:     bool,
+}
+
+impl Default for StrategyConfig {
+    fn default() -> StrategyConfig {
+        StrategyConfig {
+            read_ahead_threshold: 8 * 1024,
+            advise_threshold:     268_435_456,
+            mmap_threshold:       None,
+            min_buffer_size:      MIN_READ_BUF_SIZE,
+            max_buffer_size:      MAX_READ_BUF_SIZE,
+            direct_io:            false,
+            adaptive_buffer:      false,
+            filesystem_aware:     true,
+        }
+    }
+}
+
+impl StrategyConfig {
+    /// Checks that the buffer sizes are positive multiples of the page size and ordered
+    pub fn validate(&self) -> Result<()> {
+        if self.min_buffer_size == 0 || !self.min_buffer_size.is_multiple_of(os::PAGE_SIZE) {
+            return Err(ErrorKind::InvalidConfig("min_buffer_size is not a positive multiple of the page size").into());
+        }
+        if !self.max_buffer_size.is_multiple_of(os::PAGE_SIZE) {
+            return Err(ErrorKind::InvalidConfig("max_buffer_size is not a multiple of the page size").into());
+        }
+        if self.min_buffer_size > self.max_buffer_size {
+            return Err(ErrorKind::InvalidConfig("min_buffer_size exceeds max_buffer_size").into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn default_is_valid() {
+        asserting("Default config is valid")
+            .that(&StrategyConfig::default().validate().is_ok())
+            .is_true();
+    }
+
+    #[test]
+    fn validate_rejects_invalid_buffer_sizes() {
+        let unaligned = StrategyConfig {
+            min_buffer_size: os::PAGE_SIZE + 1,
+            ..StrategyConfig::default()
+        };
+        let empty = StrategyConfig {
+            min_buffer_size: 0,
+            ..StrategyConfig::default()
+        };
+        let unordered = StrategyConfig {
+            min_buffer_size: 2 * os::PAGE_SIZE,
+            max_buffer_size: os::PAGE_SIZE,
+            ..StrategyConfig::default()
+        };
+
+        for config in &[unaligned, empty, unordered] {
+            let res = config.validate();
+            asserting(&format!("{:?} is invalid", config))
+                .that(&res.is_err())
+                .is_true();
+        }
+    }
+}
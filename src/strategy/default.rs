@@ -0,0 +1,271 @@
+use crate::{
+    errors::*,
+    fastfile::{FastFileReader, FastFileReaderBuilder},
+    os::FileSystemKind,
+    strategy::{
+        advice_depends_on_device,
+        read_ahead_advice,
+        FileFacts,
+        PlannedBackend,
+        ReadPlan,
+        ReaderStrategy,
+        StrategyConfig,
+        SystemFacts,
+    },
+};
+
+/// `DefaultReaderStrategy` chooses the backend, advice, and buffer sizes by the file system, size,
+/// and device of a file
+///
+/// The decisions are made by `plan` from `FileFacts` and `SystemFacts`. Facts the OS does not
+/// provide are unknown, e.g. the device outside of Linux, and the strategy falls back to the
+/// thresholds of its `StrategyConfig` for them.
+#[derive(Default)]
+pub struct DefaultReaderStrategy {
+    config: StrategyConfig,
+}
+
+impl DefaultReaderStrategy {
+    /// Creates the strategy with the thresholds of `config`
+    pub fn with_config(config: StrategyConfig) -> DefaultReaderStrategy { DefaultReaderStrategy { config } }
+
+    /// Plans how to read a file described by `file` on a system described by `system`
+    pub fn plan(&self, file: &FileFacts, system: &SystemFacts) -> ReadPlan {
+        let kind = match file.filesystem {
+            Some(ref filesystem) if self.config.filesystem_aware => filesystem.kind(),
+            _ => FileSystemKind::Unknown,
+        };
+        let use_mmap = match kind {
+            // Files are in memory already, so mapping them saves copying without any I/O
+            FileSystemKind::Memory => true,
+            // Page faults would wait for the network or a user space process
+            FileSystemKind::Network | FileSystemKind::Fuse => false,
+            _ => {
+                self.config
+                    .mmap_threshold
+                    .map(|threshold| file.size >= threshold)
+                    .unwrap_or(false)
+            }
+        };
+        let backend = if use_mmap && system.can_map(file.size) {
+            PlannedBackend::Mmap
+        } else if self.config.direct_io {
+            PlannedBackend::Direct
+        } else {
+            PlannedBackend::File
+        };
+        // Reading ahead is a no-op for files in memory
+        let advice = if kind == FileSystemKind::Memory {
+            None
+        } else {
+            read_ahead_advice(file.size, &file.device, &self.config)
+        };
+
+        ReadPlan {
+            backend,
+            advice: advice.into_iter().collect(),
+            min_buffer_size: self.config.min_buffer_size,
+            max_buffer_size: self.config.max_buffer_size,
+            adaptive_buffer: self.config.adaptive_buffer,
+        }
+    }
+}
+
+impl ReaderStrategy for DefaultReaderStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        self.config.validate()?;
+        let mut facts = FileFacts::gather(&ffrb)?;
+        if advice_depends_on_device(facts.size, &self.config) {
+            facts.gather_device(&ffrb);
+        }
+        let plan = self.plan(&facts, &SystemFacts::gather());
+
+        plan.execute(ffrb.file, &facts)
+    }
+
+    fn name(&self) -> &'static str { "default" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        backend::Advice,
+        errors::ErrorKind,
+        os::{DeviceInfo, FileSystemInfo, PAGE_SIZE},
+        testing,
+        FastFileRead,
+    };
+
+    use spectral::prelude::*;
+
+    fn open(config: StrategyConfig, size: usize) -> Result<FastFileReader> {
+        testing::open_fixture(&DefaultReaderStrategy::with_config(config), size)
+    }
+
+    fn facts(size: usize, kind: FileSystemKind) -> FileFacts {
+        FileFacts {
+            size,
+            filesystem: Some(FileSystemInfo::new(kind, "test")),
+            ..FileFacts::default()
+        }
+    }
+
+    fn mmap_config() -> StrategyConfig {
+        StrategyConfig {
+            mmap_threshold: Some(64 * 1024),
+            ..StrategyConfig::default()
+        }
+    }
+
+    #[test]
+    fn plan_maps_files_in_memory_without_advice() {
+        let strategy = DefaultReaderStrategy::with_config(mmap_config());
+
+        let plan = strategy.plan(&facts(1024, FileSystemKind::Memory), &SystemFacts::default());
+
+        asserting("Backend")
+            .that(&plan.backend)
+            .is_equal_to(PlannedBackend::Mmap);
+        asserting("No advice").that(&plan.advice).is_empty();
+    }
+
+    #[test]
+    fn plan_does_not_map_files_on_network_file_systems() {
+        let strategy = DefaultReaderStrategy::with_config(mmap_config());
+
+        let network = strategy.plan(&facts(100_000, FileSystemKind::Network), &SystemFacts::default());
+        let local = strategy.plan(&facts(100_000, FileSystemKind::Local), &SystemFacts::default());
+
+        asserting("Network backend")
+            .that(&network.backend)
+            .is_equal_to(PlannedBackend::File);
+        asserting("Local backend")
+            .that(&local.backend)
+            .is_equal_to(PlannedBackend::Mmap);
+    }
+
+    #[test]
+    fn plan_ignores_file_system_unless_aware() {
+        let strategy = DefaultReaderStrategy::with_config(StrategyConfig {
+            filesystem_aware: false,
+            ..mmap_config()
+        });
+
+        let plan = strategy.plan(&facts(1024, FileSystemKind::Memory), &SystemFacts::default());
+
+        asserting("Backend")
+            .that(&plan.backend)
+            .is_equal_to(PlannedBackend::File);
+    }
+
+    #[test]
+    fn plan_does_not_map_beyond_memory_budget() {
+        let strategy = DefaultReaderStrategy::with_config(StrategyConfig {
+            direct_io: true,
+            ..mmap_config()
+        });
+        let system = SystemFacts {
+            memory_available: Some(64 * 1024),
+        };
+
+        let plan = strategy.plan(&facts(100_000, FileSystemKind::Local), &system);
+
+        asserting("Backend")
+            .that(&plan.backend)
+            .is_equal_to(PlannedBackend::Direct);
+    }
+
+    #[test]
+    fn plan_reads_ahead_as_much_as_suits_device() {
+        let strategy = DefaultReaderStrategy::with_config(StrategyConfig {
+            advise_threshold: 1024 * 1024,
+            ..StrategyConfig::default()
+        });
+        let hdd = FileFacts {
+            device: DeviceInfo {
+                rotational: Some(true),
+                read_ahead_kb: Some(128),
+                ..DeviceInfo::default()
+            },
+            ..facts(100 * 1024 * 1024, FileSystemKind::Local)
+        };
+
+        let plan = strategy.plan(&hdd, &SystemFacts::default());
+
+        asserting("Advice")
+            .that(&plan.advice)
+            .is_equal_to(vec![Advice::WillNeed(2 * 1024 * 1024)]);
+    }
+
+    #[test]
+    fn mmap_threshold_selects_mmap_backend() {
+        let config = StrategyConfig {
+            mmap_threshold: Some(64 * 1024),
+            ..StrategyConfig::default()
+        };
+
+        let small = open(config, 1024).expect("Failed to open small file");
+        let large = open(config, 100_000).expect("Failed to open large file");
+
+        asserting("Backend below threshold")
+            .that(&small.report().backend.as_str())
+            .is_equal_to("file");
+        asserting("Backend above threshold")
+            .that(&large.report().backend.as_str())
+            .is_equal_to("mmap");
+        asserting("Advice above read ahead threshold")
+            .that(&large.report().advice.len())
+            .is_equal_to(1);
+    }
+
+    #[test]
+    fn buffer_sizes_limit_read_buffer() {
+        let config = StrategyConfig {
+            read_ahead_threshold: usize::max_value(),
+            min_buffer_size: 2 * PAGE_SIZE,
+            max_buffer_size: 2 * PAGE_SIZE,
+            ..StrategyConfig::default()
+        };
+        let mut ffr = open(config, 100_000).expect("Failed to open file");
+
+        let len = FastFileRead::read(&mut ffr).expect("Failed to fastread file").len();
+
+        asserting("Chunk size")
+            .that(&len)
+            .is_less_than_or_equal_to(2 * PAGE_SIZE);
+        asserting("Buffer size")
+            .that(&ffr.report().buffer_size)
+            .is_equal_to(Some(2 * PAGE_SIZE));
+        asserting("No advice below read ahead threshold")
+            .that(&ffr.report().advice)
+            .is_empty();
+    }
+
+    #[test]
+    fn file_system_type_is_recorded() {
+        let ffr = open(StrategyConfig::default(), 1024).expect("Failed to open file");
+
+        asserting("File system type")
+            .that(&ffr.report().fs_type.is_some())
+            .is_true();
+    }
+
+    #[test]
+    fn invalid_config_fails() {
+        let config = StrategyConfig {
+            min_buffer_size: PAGE_SIZE + 1,
+            ..StrategyConfig::default()
+        };
+
+        let res = open(config, 1024);
+
+        asserting("Open fails").that(&res.is_err()).is_true();
+        asserting("Error kind")
+            .that(res.err().unwrap().kind()) // Safe, bc we checked above
+            .is_equal_to(&ErrorKind::InvalidConfig(
+                "min_buffer_size is not a positive multiple of the page size",
+            ));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::{os, strategy::StrategyConfig};
+
+use lazy_static::lazy_static;
+use std::env;
+
+const STRATEGY: &str = "FASTFILE_STRATEGY";
+const MAX_BUF: &str = "FASTFILE_MAX_BUF";
+const NO_ADVISE: &str = "FASTFILE_NO_ADVISE";
+
+lazy_static! {
+    static ref ENV_OVERRIDES: EnvOverrides = EnvOverrides::from_vars(|name| env::var(name).ok());
+}
+
+/// Backend forced by `FASTFILE_STRATEGY`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum BackendOverride {
+    /// Always read via read system calls
+    File,
+    /// Memory map every non-empty file regardless of its size and file system, as far as the
+    /// `MemoryBudget` allows
+    Mmap,
+    /// Read via read system calls bypassing the page cache
+    Direct,
+    /// Let the strategy decide
+    Auto,
+}
+
+/// Overrides of the default strategy from environment variables
+///
+/// Invalid values are ignored and recorded as warnings, so a misconfigured host keeps working.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct EnvOverrides {
+    pub(crate) backend:         Option<BackendOverride>,
+    pub(crate) max_buffer_size: Option<usize>,
+    pub(crate) no_advise:       bool,
+    pub(crate) applied:         Vec<String>,
+    pub(crate) warnings:        Vec<String>,
+}
+
+impl EnvOverrides {
+    /// Returns the overrides of this process; the environment is read once at first use
+    pub(crate) fn get() -> &'static EnvOverrides { &ENV_OVERRIDES }
+
+    /// Parses the overrides from the environment variables returned by `var`
+    pub(crate) fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> EnvOverrides {
+        let mut overrides = EnvOverrides::default();
+
+        if let Some(value) = var(STRATEGY) {
+            let expected = "expected file, mmap, direct, or auto";
+            let (backend, expected) = match value.trim().to_lowercase().as_str() {
+                "file" => (Some(BackendOverride::File), expected),
+                "mmap" => (Some(BackendOverride::Mmap), expected),
+                // `FileBackend::direct` is only available on macOS; elsewhere every open would fail
+                "direct" if cfg!(target_os = "macos") => (Some(BackendOverride::Direct), expected),
+                "direct" => (None, "direct I/O is not supported on this platform"),
+                "auto" => (Some(BackendOverride::Auto), expected),
+                _ => (None, expected),
+            };
+            overrides.record(STRATEGY, &value, backend.is_some(), expected);
+            overrides.backend = backend;
+        }
+
+        if let Some(value) = var(MAX_BUF) {
+            let max_buffer_size = value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|size| *size > 0 && size.is_multiple_of(os::PAGE_SIZE));
+            overrides.record(
+                MAX_BUF,
+                &value,
+                max_buffer_size.is_some(),
+                "expected a positive multiple of the page size in bytes",
+            );
+            overrides.max_buffer_size = max_buffer_size;
+        }
+
+        if let Some(value) = var(NO_ADVISE) {
+            let no_advise = match value.trim().to_lowercase().as_str() {
+                "1" | "true" | "yes" => Some(true),
+                "0" | "false" | "no" | "" => Some(false),
+                _ => None,
+            };
+            overrides.record(
+                NO_ADVISE,
+                &value,
+                no_advise.is_some(),
+                "expected 1, true, yes, 0, false, or no",
+            );
+            overrides.no_advise = no_advise.unwrap_or(false);
+        }
+
+        overrides
+    }
+
+    fn record(&mut self, name: &str, value: &str, valid: bool, expected: &str) {
+        if valid {
+            self.applied.push(format!("{}={}", name, value));
+        } else {
+            self.warnings
+                .push(format!("ignored {}={:?}: {}", name, value, expected));
+        }
+    }
+
+    /// Applies the overrides to `config`
+    pub(crate) fn apply(&self, mut config: StrategyConfig) -> StrategyConfig {
+        match self.backend {
+            Some(BackendOverride::File) => {
+                config.mmap_threshold = None;
+                config.direct_io = false;
+                config.filesystem_aware = false;
+            }
+            Some(BackendOverride::Mmap) => {
+                config.mmap_threshold = Some(0);
+                config.direct_io = false;
+                config.filesystem_aware = false;
+            }
+            Some(BackendOverride::Direct) => {
+                config.mmap_threshold = None;
+                config.direct_io = true;
+                config.filesystem_aware = false;
+            }
+            Some(BackendOverride::Auto) | None => {}
+        }
+        if let Some(max_buffer_size) = self.max_buffer_size {
+            config.max_buffer_size = max_buffer_size;
+            config.min_buffer_size = config.min_buffer_size.min(max_buffer_size);
+        }
+        if self.no_advise {
+            config.read_ahead_threshold = usize::MAX;
+        }
+
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(target_os = "macos"))]
+    use crate::{strategy::DefaultReaderStrategy, testing};
+
+    use spectral::prelude::*;
+    use std::collections::HashMap;
+
+    fn overrides(vars: &[(&str, &str)]) -> EnvOverrides {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        EnvOverrides::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn no_variables_keep_config() {
+        let overrides = overrides(&[]);
+
+        asserting("Config")
+            .that(&overrides.apply(StrategyConfig::default()))
+            .is_equal_to(StrategyConfig::default());
+        asserting("Applied").that(&overrides.applied).is_empty();
+        asserting("Warnings").that(&overrides.warnings).is_empty();
+    }
+
+    #[test]
+    fn valid_variables_override_config() {
+        let max_buf = (4 * os::PAGE_SIZE).to_string();
+        let overrides = overrides(&[(STRATEGY, "MMAP"), (MAX_BUF, &max_buf), (NO_ADVISE, "1")]);
+
+        let config = overrides.apply(StrategyConfig::default());
+
+        asserting("Mmap threshold")
+            .that(&config.mmap_threshold)
+            .is_equal_to(Some(0));
+        asserting("Max buffer size")
+            .that(&config.max_buffer_size)
+            .is_equal_to(4 * os::PAGE_SIZE);
+        asserting("Advice disabled")
+            .that(&config.read_ahead_threshold)
+            .is_equal_to(usize::MAX);
+        asserting("Config is valid").that(&config.validate().is_ok()).is_true();
+        asserting("Applied").that(&overrides.applied).has_length(3);
+        asserting("Warnings").that(&overrides.warnings).is_empty();
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn direct_disables_mmap() {
+        let config = overrides(&[(STRATEGY, "direct")]).apply(StrategyConfig {
+            mmap_threshold: Some(0),
+            ..StrategyConfig::default()
+        });
+
+        asserting("Mmap threshold").that(&config.mmap_threshold).is_none();
+        asserting("Direct I/O").that(&config.direct_io).is_true();
+        asserting("File system type is ignored")
+            .that(&config.filesystem_aware)
+            .is_false();
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn direct_is_ignored_where_unsupported() {
+        let overrides = overrides(&[(STRATEGY, "direct")]);
+        let config = overrides.apply(StrategyConfig::default());
+        let warning = "ignored FASTFILE_STRATEGY=\"direct\": direct I/O is not supported on this platform";
+
+        let res = testing::open_fixture(&DefaultReaderStrategy::with_config(config), 100_000);
+
+        asserting("Config").that(&config).is_equal_to(StrategyConfig::default());
+        asserting("Warnings").that(&overrides.warnings).has_length(1);
+        asserting("Warning names the platform")
+            .that(&overrides.warnings[0].as_str())
+            .is_equal_to(warning);
+        asserting("Open succeeds").that(&res.is_ok()).is_true();
+    }
+
+    #[test]
+    fn invalid_variables_are_ignored_with_warnings() {
+        let overrides = overrides(&[(STRATEGY, "fast"), (MAX_BUF, "1000"), (NO_ADVISE, "maybe")]);
+
+        asserting("Config")
+            .that(&overrides.apply(StrategyConfig::default()))
+            .is_equal_to(StrategyConfig::default());
+        asserting("Applied").that(&overrides.applied).is_empty();
+        asserting("Warnings").that(&overrides.warnings).has_length(3);
+        asserting("Warning names the variable")
+            .that(&overrides.warnings[0].as_str())
+            .is_equal_to("ignored FASTFILE_STRATEGY=\"fast\": expected file, mmap, direct, or auto");
+    }
+}
@@ -1,14 +1,223 @@
 use crate::{
+    backend::Advice,
     errors::*,
     fastfile::{FastFileReader, FastFileReaderBuilder},
+    os::DeviceInfo,
 };
 
+/// Name of strategies that do not name themselves
+pub(crate) const UNNAMED: &str = "custom";
+
 pub trait ReaderStrategy {
     fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader>;
+
+    /// Returns the name of this strategy for the `StrategyReport`
+    fn name(&self) -> &'static str { UNNAMED }
+}
+
+/// Returns the size of the file to read: the exact size, the size hint, or the size on disk
+pub fn get_file_size(ffrb: &FastFileReaderBuilder) -> Result<usize> {
+    let size = if let Some(size) = ffrb.size {
+        size
+    } else if let Some(size_hint) = ffrb.size_hint {
+        size_hint
+    } else {
+        let file = &ffrb.file;
+        let meta = file
+            .metadata()
+            .map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_known_path(ffrb.path.as_ref()))?;
+        meta.len() as usize
+    };
+
+    Ok(size)
+}
+
+/// Advises `reader` to read ahead according to the thresholds of `config`
+///
+/// Files of at least `read_ahead_threshold` bytes are advised to be read sequentially, or to be
+/// read entirely if they are larger than `advise_threshold`. The advice goes through the reader
+/// rather than the file descriptor, so it reaches whichever backend the strategy has chosen and is
+/// recorded in the `StrategyReport`.
+pub fn prepare_file_for_reading(reader: &mut FastFileReader, file_size: usize, config: &StrategyConfig) -> Result<()> {
+    if let Some(advice) = advice_for(file_size, file_size, config) {
+        reader.advise(advice)?;
+    }
+
+    Ok(())
 }
 
-#[cfg(target_os = "macos")]
-mod macos;
+/// Like `prepare_file_for_reading`, but files larger than `advise_threshold` are advised to
+/// read ahead as much as suits `device` instead of the entire file
+pub fn prepare_file_for_device(
+    reader: &mut FastFileReader,
+    file_size: usize,
+    device: &DeviceInfo,
+    config: &StrategyConfig,
+) -> Result<()> {
+    if let Some(advice) = read_ahead_advice(file_size, device, config) {
+        reader.advise(advice)?;
+    }
 
-#[cfg(target_os = "macos")]
-pub use macos::DefaultMacOsReaderStrategy as DefaultReaderStrategy;
+    Ok(())
+}
+
+/// Returns the advice `prepare_file_for_device` passes on for a file of `file_size` bytes
+pub fn read_ahead_advice(file_size: usize, device: &DeviceInfo, config: &StrategyConfig) -> Option<Advice> {
+    let read_ahead = read_ahead_size(device)
+        .map(|size| size.min(file_size))
+        .unwrap_or(file_size);
+    advice_for(file_size, read_ahead, config)
+}
+
+/// Returns whether `read_ahead_advice` depends on the device for a file of `file_size` bytes
+///
+/// Strategies gather the device only in that case, since looking it up costs several system calls
+/// on every open.
+pub fn advice_depends_on_device(file_size: usize, config: &StrategyConfig) -> bool {
+    file_size >= config.read_ahead_threshold && file_size > config.advise_threshold
+}
+
+/// Returns how many bytes to read ahead on `device`; `None` if the device is unknown
+///
+/// The kernel's read-ahead window is scaled up, by 16 for rotating disks, which pay for every
+/// seek, and by an eighth of the queue depth for SSDs, which serve deep queues in parallel.
+pub fn read_ahead_size(device: &DeviceInfo) -> Option<usize> {
+    let window = device.read_ahead_kb.filter(|kb| *kb > 0)? * 1024;
+    let factor = match device.rotational {
+        Some(true) => 16,
+        _ => device.queue_depth.map(|depth| (depth / 8).max(1)).unwrap_or(1),
+    };
+
+    Some(window.saturating_mul(factor))
+}
+
+fn advice_for(file_size: usize, read_ahead: usize, config: &StrategyConfig) -> Option<Advice> {
+    if file_size < config.read_ahead_threshold {
+        None
+    } else if file_size <= config.advise_threshold {
+        Some(Advice::Sequential)
+    } else {
+        Some(Advice::WillNeed(read_ahead))
+    }
+}
+
+mod cache_aware;
+mod combinators;
+mod config;
+mod default;
+mod env;
+mod plan;
+#[cfg(feature = "calibration")]
+mod profiled;
+mod report;
+
+pub use cache_aware::CacheAwareReaderStrategy;
+pub use combinators::{Fallback, SizeSwitch, WithAdvice};
+pub use config::StrategyConfig;
+pub use default::DefaultReaderStrategy;
+pub(crate) use env::EnvOverrides;
+pub use plan::{FileFacts, PlannedBackend, ReadPlan, SystemFacts};
+#[cfg(feature = "calibration")]
+pub use profiled::{Profile, ProfileBackend, ProfiledReaderStrategy, SizeClass};
+pub use report::{AdviceReport, StrategyReport};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use spectral::prelude::*;
+
+    #[test]
+    fn read_ahead_size_scales_with_device() {
+        let hdd = DeviceInfo {
+            rotational: Some(true),
+            read_ahead_kb: Some(128),
+            ..DeviceInfo::default()
+        };
+        let ssd = DeviceInfo {
+            rotational: Some(false),
+            read_ahead_kb: Some(128),
+            queue_depth: Some(1024),
+            ..DeviceInfo::default()
+        };
+        let disabled = DeviceInfo {
+            read_ahead_kb: Some(0),
+            ..hdd.clone()
+        };
+
+        asserting("HDD")
+            .that(&read_ahead_size(&hdd))
+            .is_equal_to(Some(2 * 1024 * 1024));
+        asserting("SSD")
+            .that(&read_ahead_size(&ssd))
+            .is_equal_to(Some(16 * 1024 * 1024));
+        asserting("Read-ahead disabled")
+            .that(&read_ahead_size(&disabled))
+            .is_none();
+        asserting("Unknown device")
+            .that(&read_ahead_size(&DeviceInfo::default()))
+            .is_none();
+    }
+
+    #[test]
+    fn only_advice_for_large_files_depends_on_device() {
+        let config = StrategyConfig {
+            advise_threshold: 1024 * 1024,
+            ..StrategyConfig::default()
+        };
+
+        asserting("Small file")
+            .that(&advice_depends_on_device(1024, &config))
+            .is_false();
+        asserting("File up to advise threshold")
+            .that(&advice_depends_on_device(1024 * 1024, &config))
+            .is_false();
+        asserting("File beyond advise threshold")
+            .that(&advice_depends_on_device(1024 * 1024 + 1, &config))
+            .is_true();
+        asserting("Advice disabled")
+            .that(&advice_depends_on_device(
+                usize::MAX - 1,
+                &StrategyConfig {
+                    read_ahead_threshold: usize::MAX,
+                    ..config
+                },
+            ))
+            .is_false();
+    }
+
+    #[test]
+    fn large_files_read_ahead_as_much_as_suits_device() {
+        struct Advised;
+        impl crate::backend::Backend for Advised {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> { Ok(0) }
+
+            fn size(&self) -> std::io::Result<u64> { Ok(0) }
+        }
+        let config = StrategyConfig {
+            advise_threshold: 1024 * 1024,
+            ..StrategyConfig::default()
+        };
+        let ssd = DeviceInfo {
+            read_ahead_kb: Some(128),
+            queue_depth: Some(64),
+            ..DeviceInfo::default()
+        };
+        let mut reader = FastFileReader::new(Box::new(Advised), 0);
+
+        prepare_file_for_device(&mut reader, 100 * 1024 * 1024, &ssd, &config).expect("Failed to advise");
+        prepare_file_for_device(&mut reader, 100 * 1024 * 1024, &DeviceInfo::default(), &config)
+            .expect("Failed to advise");
+
+        asserting("Advice")
+            .that(
+                &reader
+                    .report()
+                    .advice
+                    .iter()
+                    .map(|advice| advice.advice)
+                    .collect::<Vec<_>>(),
+            )
+            .is_equal_to(vec![Advice::WillNeed(1024 * 1024), Advice::WillNeed(100 * 1024 * 1024)]);
+    }
+}
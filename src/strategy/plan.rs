@@ -0,0 +1,225 @@
+use crate::{
+    backend::{self, Advice, Backend, FileBackend},
+    budget::MemoryBudget,
+    errors::*,
+    fastfile::{FastFileReader, FastFileReaderBuilder},
+    os::{self, DeviceInfo, FileSystemInfo},
+    strategy::get_file_size,
+};
+
+use std::{fs::File, os::unix::io::AsRawFd};
+
+/// `FileFacts` are what strategies know about a file when they plan how to read it
+///
+/// `gather` asks the OS; tests construct facts about hypothetical files instead. Facts that could
+/// not be determined are `None` or unknown.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileFacts {
+    /// Size of the file in bytes as determined by `get_file_size`
+    pub size:            usize,
+    /// File system the file resides on
+    pub filesystem:      Option<FileSystemInfo>,
+    /// Block device the file resides on; only gathered by strategies whose plan depends on it
+    pub device:          DeviceInfo,
+    /// Ratio of the pages of the file in the page cache; only gathered by strategies that use it
+    pub cache_residency: Option<f32>,
+}
+
+impl FileFacts {
+    /// Gathers the size and file system of the file of `ffrb`
+    ///
+    /// The device is left unknown, because looking it up takes several system calls; see
+    /// `gather_device`.
+    pub fn gather(ffrb: &FastFileReaderBuilder) -> Result<FileFacts> {
+        let fd = ffrb.file.as_raw_fd();
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        let filesystem = os::filesystem_info(fd).ok();
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        let filesystem = None;
+
+        Ok(FileFacts {
+            size: get_file_size(ffrb)?,
+            filesystem,
+            device: DeviceInfo::default(),
+            cache_residency: None,
+        })
+    }
+
+    /// Gathers the device of the file of `ffrb`; it stays unknown if it cannot be determined
+    pub fn gather_device(&mut self, ffrb: &FastFileReaderBuilder) {
+        self.device = os::device_info(ffrb.file.as_raw_fd()).unwrap_or_default();
+    }
+}
+
+/// `SystemFacts` are what strategies know about the system when they plan how to read a file
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SystemFacts {
+    /// Bytes left in the global `MemoryBudget`; `None` if it is unlimited
+    pub memory_available: Option<usize>,
+}
+
+impl SystemFacts {
+    /// Gathers the current state of the system
+    pub fn gather() -> SystemFacts {
+        SystemFacts {
+            memory_available: MemoryBudget::global().available(),
+        }
+    }
+
+    /// Returns whether a file of `size` bytes may be memory mapped within the memory budget
+    pub fn can_map(&self, size: usize) -> bool {
+        size > 0 && self.memory_available.map(|available| available >= size).unwrap_or(true)
+    }
+}
+
+/// Backend a `ReadPlan` reads from
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PlannedBackend {
+    /// Read system calls
+    File,
+    /// Read system calls bypassing the page cache
+    Direct,
+    /// Memory mapping; falls back to `File` if the memory budget is exhausted when executed
+    Mmap,
+}
+
+/// `ReadPlan` is the decision of a strategy how to read a file
+///
+/// Plans are computed from `FileFacts` and `SystemFacts` without touching the file, so they can be
+/// inspected and tested for hypothetical files and systems, and then executed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ReadPlan {
+    /// Backend to read from
+    pub backend:         PlannedBackend,
+    /// Advice to pass on to the backend in order
+    pub advice:          Vec<Advice>,
+    /// Minimum size of the read buffer in bytes
+    pub min_buffer_size: usize,
+    /// Maximum size of the read buffer in bytes
+    pub max_buffer_size: usize,
+    /// Whether the read buffer size is tuned while reading
+    pub adaptive_buffer: bool,
+}
+
+impl ReadPlan {
+    /// Opens a reader for `file` as planned and records `facts` in its `StrategyReport`
+    ///
+    /// Fails if the backend cannot be created or advice fails.
+    pub fn execute(&self, file: File, facts: &FileFacts) -> Result<FastFileReader> {
+        let inner: Box<dyn Backend> = match self.backend {
+            PlannedBackend::File => Box::new(FileBackend::new(file)),
+            PlannedBackend::Direct => direct_backend(file)?,
+            PlannedBackend::Mmap => backend::mmap_or_file(file)?,
+        };
+        let mut reader = FastFileReader::new(inner, facts.size);
+        reader.set_buffer_sizes(self.min_buffer_size, self.max_buffer_size);
+        reader.set_adaptive_buffer(self.adaptive_buffer);
+        for advice in &self.advice {
+            reader.advise(*advice)?;
+        }
+
+        let report = reader.report_mut();
+        report.fs_type = facts
+            .filesystem
+            .as_ref()
+            .map(|filesystem| filesystem.name().to_string());
+        if !facts.device.is_unknown() {
+            report.device = Some(facts.device.clone());
+        }
+        if facts.cache_residency.is_some() {
+            report.cache_residency = facts.cache_residency;
+        }
+
+        Ok(reader)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn direct_backend(file: File) -> Result<Box<dyn Backend>> { Ok(Box::new(FileBackend::direct(file)?)) }
+
+#[cfg(not(target_os = "macos"))]
+fn direct_backend(_file: File) -> Result<Box<dyn Backend>> { Err(ErrorKind::Unsupported("direct I/O").into()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{fastfile::FastFile, os::FileSystemKind, testing, FastFileRead};
+
+    use spectral::prelude::*;
+
+    fn plan(backend: PlannedBackend, advice: Vec<Advice>) -> ReadPlan {
+        ReadPlan {
+            backend,
+            advice,
+            min_buffer_size: 4096,
+            max_buffer_size: 64 * 1024,
+            adaptive_buffer: false,
+        }
+    }
+
+    #[test]
+    fn execute_follows_plan_and_records_facts() {
+        let fixture = testing::fixture(100_000, 1).expect("Failed to create test file");
+        let file = File::open(fixture.path()).expect("Failed to open test file");
+        let facts = FileFacts {
+            size:            100_000,
+            filesystem:      Some(FileSystemInfo::new(FileSystemKind::Local, "ext4")),
+            device:          DeviceInfo {
+                rotational: Some(false),
+                ..DeviceInfo::default()
+            },
+            cache_residency: Some(0.5),
+        };
+
+        let mut ffr = plan(PlannedBackend::Mmap, vec![Advice::WillNeed(100_000)])
+            .execute(file, &facts)
+            .expect("Failed to execute plan");
+        let contents = FastFileRead::read_to_end(&mut ffr)
+            .expect("Failed to fastread file")
+            .to_vec();
+        let report = ffr.report();
+
+        asserting("Backend").that(&report.backend.as_str()).is_equal_to("mmap");
+        asserting("Advice")
+            .that(&report.advice.iter().map(|advice| advice.advice).collect::<Vec<_>>())
+            .is_equal_to(vec![Advice::WillNeed(100_000)]);
+        asserting("File system")
+            .that(&report.fs_type)
+            .is_equal_to(Some("ext4".to_string()));
+        asserting("Device")
+            .that(&report.device)
+            .is_equal_to(Some(facts.device.clone()));
+        asserting("Cache residency")
+            .that(&report.cache_residency)
+            .is_equal_to(Some(0.5));
+        asserting("Contents").that(&(contents == fixture.contents())).is_true();
+    }
+
+    #[test]
+    fn gather_reads_file_facts() {
+        let fixture = testing::fixture(1000, 1).expect("Failed to create test file");
+        let ffrb = FastFile::read(fixture.path()).expect("Failed to create FastFileReaderBuilder");
+
+        let facts = FileFacts::gather(&ffrb).expect("Failed to gather facts");
+
+        asserting("Size").that(&facts.size).is_equal_to(1000);
+        asserting("Device is not gathered")
+            .that(&facts.device.is_unknown())
+            .is_true();
+        asserting("Cache residency").that(&facts.cache_residency).is_none();
+    }
+
+    #[test]
+    fn can_map_within_memory_budget() {
+        let unlimited = SystemFacts::default();
+        let limited = SystemFacts {
+            memory_available: Some(1000),
+        };
+
+        asserting("Unlimited").that(&unlimited.can_map(1_000_000)).is_true();
+        asserting("Empty file").that(&unlimited.can_map(0)).is_false();
+        asserting("Within budget").that(&limited.can_map(1000)).is_true();
+        asserting("Exceeds budget").that(&limited.can_map(1001)).is_false();
+    }
+}
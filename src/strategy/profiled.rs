@@ -0,0 +1,295 @@
+use crate::{
+    errors::*,
+    fastfile::{FastFileReader, FastFileReaderBuilder, MAX_READ_BUF_SIZE, MIN_READ_BUF_SIZE},
+    os,
+    strategy::{FileFacts, PlannedBackend, ReadPlan, ReaderStrategy, SystemFacts},
+};
+
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Backend chosen for a size class of a `Profile`
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileBackend {
+    /// Read via read system calls
+    File,
+    /// Memory map files of the size class; they are read via read system calls instead while the
+    /// `MemoryBudget` is exhausted
+    Mmap,
+}
+
+/// `SizeClass` is the tuned setup for files up to a size
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SizeClass {
+    /// Largest file size in bytes of this class; `None` for the last class without a limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size:    Option<usize>,
+    /// Backend to read files of this class with
+    pub backend:     ProfileBackend,
+    /// Maximal read buffer size; must be a multiple of the page size
+    pub buffer_size: usize,
+}
+
+/// `Profile` describes the fastest setup per size class on a machine
+///
+/// Profiles are created by `calibration::calibrate` and stored as TOML.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Size classes in ascending order of `max_size`
+    pub size_classes: Vec<SizeClass>,
+}
+
+impl Profile {
+    /// Loads a profile from the TOML file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Profile> {
+        let path = path.as_ref();
+        let toml =
+            fs::read_to_string(path).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_path(path))?;
+        Profile::from_toml(&toml).map_err(|e| e.with_path(path))
+    }
+
+    /// Stores this profile as TOML file at `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_toml()?).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e).with_path(path))
+    }
+
+    /// Parses and validates a profile from TOML
+    pub fn from_toml(toml: &str) -> Result<Profile> {
+        let profile: Profile =
+            toml::from_str(toml).map_err(|e| Error::with_source(ErrorKind::InvalidConfig("malformed profile"), e))?;
+        profile.validate()?;
+
+        Ok(profile)
+    }
+
+    /// Serializes this profile to TOML
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| Error::with_source(ErrorKind::InvalidConfig("unserializable profile"), e))
+    }
+
+    /// Checks that size classes are ascending and buffer sizes are positive multiples of the page
+    /// size
+    pub fn validate(&self) -> Result<()> {
+        if self.size_classes.is_empty() {
+            return Err(ErrorKind::InvalidConfig("profile has no size classes").into());
+        }
+        let mut previous = None;
+        for (i, class) in self.size_classes.iter().enumerate() {
+            if class.buffer_size == 0 || class.buffer_size % os::PAGE_SIZE != 0 {
+                return Err(ErrorKind::InvalidConfig("buffer_size is not a positive multiple of the page size").into());
+            }
+            match class.max_size {
+                Some(max_size) if previous.map(|p| p >= max_size).unwrap_or(false) => {
+                    return Err(ErrorKind::InvalidConfig("size classes are not ascending").into());
+                }
+                Some(max_size) => previous = Some(max_size),
+                None if i + 1 < self.size_classes.len() => {
+                    return Err(ErrorKind::InvalidConfig("only the last size class may be unlimited").into());
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size class of files of `size` bytes
+    ///
+    /// Files larger than the last class are assigned to the last class.
+    pub fn size_class(&self, size: usize) -> Option<&SizeClass> {
+        self.size_classes
+            .iter()
+            .find(|class| class.max_size.map(|max_size| size <= max_size).unwrap_or(true))
+            .or_else(|| self.size_classes.last())
+    }
+}
+
+/// `ProfiledReaderStrategy` picks backend and buffer size per size class from a `Profile`
+pub struct ProfiledReaderStrategy {
+    profile: Profile,
+}
+
+impl ProfiledReaderStrategy {
+    /// Creates the strategy for a validated `profile`
+    pub fn new(profile: Profile) -> Result<ProfiledReaderStrategy> {
+        profile.validate()?;
+        Ok(ProfiledReaderStrategy { profile })
+    }
+
+    /// Creates the strategy for the profile stored at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ProfiledReaderStrategy> {
+        ProfiledReaderStrategy::new(Profile::load(path)?)
+    }
+
+    /// Returns the profile of this strategy
+    pub fn profile(&self) -> &Profile { &self.profile }
+
+    /// Plans how to read a file described by `file` on a system described by `system`
+    pub fn plan(&self, file: &FileFacts, system: &SystemFacts) -> ReadPlan {
+        // Validated profiles have at least one size class
+        let (backend, buffer_size) = match self.profile.size_class(file.size) {
+            Some(class) if class.backend == ProfileBackend::Mmap && system.can_map(file.size) => {
+                (PlannedBackend::Mmap, class.buffer_size)
+            }
+            Some(class) => (PlannedBackend::File, class.buffer_size),
+            None => (PlannedBackend::File, MAX_READ_BUF_SIZE),
+        };
+
+        ReadPlan {
+            backend,
+            advice: Vec::new(),
+            min_buffer_size: MIN_READ_BUF_SIZE.min(buffer_size),
+            max_buffer_size: buffer_size,
+            adaptive_buffer: false,
+        }
+    }
+}
+
+impl ReaderStrategy for ProfiledReaderStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let facts = FileFacts::gather(&ffrb)?;
+        let plan = self.plan(&facts, &SystemFacts::gather());
+
+        plan.execute(ffrb.file, &facts)
+    }
+
+    fn name(&self) -> &'static str { "profiled" }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{fastfile::FastFile, testing, FastFileRead};
+
+    use spectral::prelude::*;
+
+    fn profile() -> Profile {
+        Profile {
+            size_classes: vec![
+                SizeClass {
+                    max_size:    Some(64 * 1024),
+                    backend:     ProfileBackend::File,
+                    buffer_size: os::PAGE_SIZE,
+                },
+                SizeClass {
+                    max_size:    None,
+                    backend:     ProfileBackend::Mmap,
+                    buffer_size: 4 * os::PAGE_SIZE,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn profile_roundtrips_as_toml() {
+        let profile = profile();
+
+        let toml = profile.to_toml().expect("Failed to serialize profile");
+        let parsed = Profile::from_toml(&toml).expect("Failed to parse profile");
+
+        asserting("Parsed profile").that(&parsed).is_equal_to(profile);
+    }
+
+    #[test]
+    fn profile_parses_toml() {
+        let toml = format!(
+            "[[size_classes]]\nmax_size = 1024\nbackend = \"mmap\"\nbuffer_size = {}\n",
+            os::PAGE_SIZE
+        );
+
+        let profile = Profile::from_toml(&toml).expect("Failed to parse profile");
+
+        asserting("Backend")
+            .that(&profile.size_classes[0].backend)
+            .is_equal_to(ProfileBackend::Mmap);
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        let mut unordered = profile();
+        unordered.size_classes.reverse();
+        let mut unaligned = profile();
+        unaligned.size_classes[0].buffer_size = os::PAGE_SIZE + 1;
+
+        for profile in &[Profile::default(), unordered, unaligned] {
+            asserting(&format!("{:?} is invalid", profile))
+                .that(&profile.validate().is_err())
+                .is_true();
+        }
+        asserting("Malformed TOML")
+            .that(&Profile::from_toml("size_classes = 1").is_err())
+            .is_true();
+    }
+
+    #[test]
+    fn size_class_is_chosen_by_size() {
+        let profile = profile();
+
+        asserting("Small file")
+            .that(&profile.size_class(1000).map(|class| class.backend))
+            .is_equal_to(Some(ProfileBackend::File));
+        asserting("Class limit is inclusive")
+            .that(&profile.size_class(64 * 1024).map(|class| class.backend))
+            .is_equal_to(Some(ProfileBackend::File));
+        asserting("Large file")
+            .that(&profile.size_class(64 * 1024 + 1).map(|class| class.backend))
+            .is_equal_to(Some(ProfileBackend::Mmap));
+    }
+
+    #[test]
+    fn strategy_uses_size_class() {
+        let strategy = ProfiledReaderStrategy::new(profile()).expect("Failed to create strategy");
+        let small = testing::fixture(1000, 1).expect("Failed to create test file");
+        let large = testing::fixture(100_000, 2).expect("Failed to create test file");
+
+        let mut small = FastFile::read(small.path())
+            .expect("Failed to create FastFileReaderBuilder")
+            .open_with_strategy(&strategy)
+            .expect("Failed to open path as FastFile");
+        let large = FastFile::read(large.path())
+            .expect("Failed to create FastFileReaderBuilder")
+            .open_with_strategy(&strategy)
+            .expect("Failed to open path as FastFile");
+        FastFileRead::read(&mut small).expect("Failed to fastread file");
+
+        asserting("Strategy")
+            .that(&small.report().strategy)
+            .is_equal_to("profiled");
+        asserting("Small file backend")
+            .that(&small.report().backend.as_str())
+            .is_equal_to("file");
+        asserting("Small file buffer size")
+            .that(&small.report().buffer_size)
+            .is_equal_to(Some(os::PAGE_SIZE));
+        asserting("Large file backend")
+            .that(&large.report().backend.as_str())
+            .is_equal_to("mmap");
+    }
+
+    #[test]
+    fn plan_maps_within_memory_budget_only() {
+        let strategy = ProfiledReaderStrategy::new(profile()).expect("Failed to create strategy");
+        let file = FileFacts {
+            size: 100_000,
+            ..FileFacts::default()
+        };
+        let exhausted = SystemFacts {
+            memory_available: Some(0),
+        };
+
+        let plan = strategy.plan(&file, &SystemFacts::default());
+
+        asserting("Backend")
+            .that(&plan.backend)
+            .is_equal_to(PlannedBackend::Mmap);
+        asserting("Buffer size")
+            .that(&plan.max_buffer_size)
+            .is_equal_to(4 * os::PAGE_SIZE);
+        asserting("Backend beyond memory budget")
+            .that(&strategy.plan(&file, &exhausted).backend)
+            .is_equal_to(PlannedBackend::File);
+    }
+}
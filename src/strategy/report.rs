@@ -0,0 +1,116 @@
+use crate::{backend::Advice, os::DeviceInfo, strategy::UNNAMED};
+
+use std::fmt;
+
+/// `StrategyReport` explains how a `FastFileReader` has been set up
+///
+/// It records the inputs a `ReaderStrategy` has seen and the decisions it has made. Inputs that
+/// the strategy did not consider are `None`. The report is kept up to date while reading, e.g.
+/// the buffer size is recorded once the read buffer has been allocated.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrategyReport {
+    /// Name of the strategy that opened the reader
+    pub strategy:        &'static str,
+    /// Size of the file in bytes
+    pub size:            usize,
+    /// Size hint given to the `FastFileReaderBuilder`
+    pub size_hint:       Option<usize>,
+    /// Type of the file system the file resides on
+    pub fs_type:         Option<String>,
+    /// Block device the file resides on
+    pub device:          Option<DeviceInfo>,
+    /// Ratio of the pages of the file that were in the page cache when the file was opened; only
+    /// recorded by strategies that sample it
+    pub cache_residency: Option<f32>,
+    /// Name of the backend the reader reads from
+    pub backend:         String,
+    /// Whether the backend hands out slices instead of copying into a read buffer
+    pub zero_copy:       bool,
+    /// Size of the read buffer in bytes; `None` until the buffer has been allocated
+    pub buffer_size:     Option<usize>,
+    /// Advice passed on to the backend and its results
+    pub advice:          Vec<AdviceReport>,
+    /// Overrides from environment variables that have been applied
+    pub overrides:       Vec<String>,
+    /// Invalid settings that have been ignored
+    pub warnings:        Vec<String>,
+}
+
+/// `AdviceReport` records advice passed on to a backend and its result
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AdviceReport {
+    /// The advice
+    pub advice: Advice,
+    /// The error message if the advice failed
+    pub error:  Option<String>,
+}
+
+impl StrategyReport {
+    pub(crate) fn new(backend: String, zero_copy: bool, size: usize) -> StrategyReport {
+        StrategyReport {
+            strategy: UNNAMED,
+            size,
+            size_hint: None,
+            fs_type: None,
+            device: None,
+            cache_residency: None,
+            backend,
+            zero_copy,
+            buffer_size: None,
+            advice: Vec::new(),
+            overrides: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for StrategyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "strategy: {}", self.strategy)?;
+
+        write!(f, "inputs: size {} bytes", self.size)?;
+        if let Some(size_hint) = self.size_hint {
+            write!(f, ", size hint {} bytes", size_hint)?;
+        }
+        if let Some(ref fs_type) = self.fs_type {
+            write!(f, ", fs type {}", fs_type)?;
+        }
+        if let Some(ref device) = self.device {
+            write!(f, ", device {}", device)?;
+        }
+        if let Some(cache_residency) = self.cache_residency {
+            write!(f, ", cache residency {:.1}%", 100.0 * cache_residency)?;
+        }
+        writeln!(f)?;
+
+        write!(f, "backend: {}", self.backend)?;
+        if self.zero_copy {
+            write!(f, " (zero-copy)")?;
+        }
+        writeln!(f)?;
+
+        match self.buffer_size {
+            Some(buffer_size) => writeln!(f, "buffer size: {} bytes", buffer_size)?,
+            None => writeln!(f, "buffer size: not allocated")?,
+        }
+
+        write!(f, "advice:")?;
+        if self.advice.is_empty() {
+            write!(f, " none")?;
+        }
+        for advice in &self.advice {
+            match advice.error {
+                Some(ref error) => write!(f, " {:?} failed ({});", advice.advice, error)?,
+                None => write!(f, " {:?} ok;", advice.advice)?,
+            }
+        }
+        if !self.overrides.is_empty() {
+            write!(f, "\noverrides: {}", self.overrides.join(", "))?;
+        }
+        if !self.warnings.is_empty() {
+            write!(f, "\nwarnings: {}", self.warnings.join("; "))?;
+        }
+
+        Ok(())
+    }
+}
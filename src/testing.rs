@@ -0,0 +1,368 @@
+use crate::{
+    backend::{Advice, Backend, FileBackend, MmapBackend, SliceCursor},
+    errors::*,
+    fastfile::{FastFile, FastFileReader, FastFileReaderBuilder, ReadErrorPolicy},
+    fixture_data::FixtureData,
+    strategy::{get_file_size, ReaderStrategy},
+};
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::Path,
+};
+use tempfile::NamedTempFile;
+
+/// Creates a `FastFileReader` reading from `backend`
+pub fn reader<B: Backend + 'static>(backend: B) -> Result<FastFileReader> {
+    let size = backend.size()?;
+    Ok(FastFileReader::new(Box::new(backend), size as usize))
+}
+
+/// Creates a `FastFileReader` reading from `backend` that handles failed reads according to
+/// `policy`
+pub fn reader_with_policy<B: Backend + 'static>(backend: B, policy: ReadErrorPolicy) -> Result<FastFileReader> {
+    let mut reader = reader(backend)?;
+    reader.set_read_error_policy(policy);
+    Ok(reader)
+}
+
+/// Returns `size` pseudo random bytes that are fully determined by `seed`
+pub fn fixture_bytes(size: usize, seed: u64) -> Vec<u8> {
+    let mut bytes = vec![0u8; size];
+    FixtureData::new(seed).fill(&mut bytes);
+    bytes
+}
+
+/// Creates a temporary file of `size` pseudo random bytes that are fully determined by `seed`
+///
+/// The content equals `fixture_bytes(size, seed)`.
+pub fn fixture(size: usize, seed: u64) -> io::Result<Fixture> {
+    let file = NamedTempFile::new()?;
+    {
+        let mut writer = BufWriter::new(file.as_file());
+        let mut data = FixtureData::new(seed);
+        let mut buf = [0u8; 8 * 1024];
+        let mut remaining = size;
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            data.fill(&mut buf[..len]);
+            writer.write_all(&buf[..len])?;
+            remaining -= len;
+        }
+        writer.flush()?;
+    }
+    file.as_file().sync_all()?;
+
+    Ok(Fixture { file, size, seed })
+}
+
+/// `Fixture` is a temporary file with deterministic content that is deleted when dropped
+pub struct Fixture {
+    file: NamedTempFile,
+    size: usize,
+    seed: u64,
+}
+
+impl Fixture {
+    /// Returns the path of the file
+    pub fn path(&self) -> &Path { self.file.path() }
+
+    /// Returns the size of the file in bytes
+    pub fn size(&self) -> usize { self.size }
+
+    /// Returns the expected content of the file
+    pub fn contents(&self) -> Vec<u8> { fixture_bytes(self.size, self.seed) }
+
+    /// Opens the file for reading
+    pub fn open(&self) -> io::Result<File> { File::open(self.path()) }
+}
+
+impl AsRef<Path> for Fixture {
+    fn as_ref(&self) -> &Path { self.path() }
+}
+
+/// Opens a fixture file of `size` bytes with `strategy`; the content is `fixture_bytes(size, size)`
+pub fn open_fixture<T: ReaderStrategy + ?Sized>(strategy: &T, size: usize) -> Result<FastFileReader> {
+    let fixture = fixture(size, size as u64).map_err(|e| Error::with_source(ErrorKind::FileOpFailed, e))?;
+    FastFile::read(fixture.path())?.open_with_strategy(strategy)
+}
+
+/// `FileStrategy` reads every file via read system calls
+pub struct FileStrategy;
+
+impl ReaderStrategy for FileStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let size = get_file_size(&ffrb)?;
+        Ok(FastFileReader::new(Box::new(FileBackend::new(ffrb.file)), size))
+    }
+
+    fn name(&self) -> &'static str { "file" }
+}
+
+/// `MmapStrategy` memory maps every file and fails if that is not possible, e.g. for empty files
+pub struct MmapStrategy;
+
+impl ReaderStrategy for MmapStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> Result<FastFileReader> {
+        let size = get_file_size(&ffrb)?;
+        Ok(FastFileReader::new(Box::new(MmapBackend::new(ffrb.file)?), size))
+    }
+
+    fn name(&self) -> &'static str { "mmap" }
+}
+
+/// `MemoryBackend` serves its data from memory and supports zero-copy reads
+pub struct MemoryBackend {
+    data:   Vec<u8>,
+    cursor: SliceCursor,
+}
+
+impl MemoryBackend {
+    /// Creates a new backend serving `data`
+    pub fn new<T: Into<Vec<u8>>>(data: T) -> MemoryBackend {
+        MemoryBackend {
+            data:   data.into(),
+            cursor: SliceCursor::default(),
+        }
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> { Ok(self.cursor.read(&self.data, buf)) }
+
+    fn size(&self) -> io::Result<u64> { Ok(self.data.len() as u64) }
+
+    fn name(&self) -> &'static str { "memory" }
+
+    fn is_zero_copy(&self) -> bool { true }
+
+    fn read_slice(&mut self, max_len: usize) -> io::Result<&[u8]> { Ok(self.cursor.read_slice(&self.data, max_len)) }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        Ok(SliceCursor::read_at(&self.data, buf, offset))
+    }
+
+    fn skip(&mut self, bytes: u64) -> io::Result<()> {
+        self.cursor.skip(&self.data, bytes);
+        Ok(())
+    }
+}
+
+/// `FaultyBackend` wraps another backend and injects faults into its sequential reads
+///
+/// Faults are configured by the builder methods and refer to byte offsets of the wrapped data.
+/// Zero-copy reads are not supported, so readers always use the buffered read path.
+pub struct FaultyBackend<B: Backend> {
+    inner:        B,
+    offset:       u64,
+    interrupts:   Vec<u64>,
+    max_read_len: Option<usize>,
+    failures:     Vec<(Range<u64>, i32)>,
+    truncate_at:  Option<u64>,
+}
+
+impl<B: Backend> FaultyBackend<B> {
+    /// Wraps `inner` without any faults
+    pub fn new(inner: B) -> FaultyBackend<B> {
+        FaultyBackend {
+            inner,
+            offset: 0,
+            interrupts: Vec::new(),
+            max_read_len: None,
+            failures: Vec::new(),
+            truncate_at: None,
+        }
+    }
+
+    /// Fails the first read that would return the byte at `offset` with `EINTR`
+    pub fn interrupt_at(mut self, offset: u64) -> Self {
+        self.interrupts.push(offset);
+        self
+    }
+
+    /// Returns at most `max_len` bytes per read
+    pub fn short_reads(mut self, max_len: usize) -> Self {
+        self.max_read_len = Some(max_len.max(1));
+        self
+    }
+
+    /// Fails every read starting within `range` with `EIO`; reads before it stop short of it
+    pub fn fail_range(self, range: Range<u64>) -> Self { self.fail_range_with(range, libc::EIO) }
+
+    /// Fails every read starting within `range` with `errno`; reads before it stop short of it
+    pub fn fail_range_with(mut self, range: Range<u64>, errno: i32) -> Self {
+        self.failures.push((range, errno));
+        self
+    }
+
+    /// Fails every read of the byte at `offset` with `EIO`
+    pub fn fail_at(self, offset: u64) -> Self { self.fail_range(offset..offset + 1) }
+
+    /// Ends the data at `offset` although `size` still reports the size of the wrapped backend
+    pub fn truncate_at(mut self, offset: u64) -> Self {
+        self.truncate_at = Some(offset);
+        self
+    }
+
+    /// Returns the wrapped backend
+    pub fn into_inner(self) -> B { self.inner }
+}
+
+impl<B: Backend> Backend for FaultyBackend<B> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = self.offset;
+        let mut end = start + buf.len() as u64;
+        if let Some(max_len) = self.max_read_len {
+            end = end.min(start + max_len as u64);
+        }
+        if let Some(truncate_at) = self.truncate_at {
+            end = end.min(truncate_at.max(start));
+        }
+        if end == start {
+            return Ok(0);
+        }
+
+        if let Some(pos) = self.interrupts.iter().position(|x| *x >= start && *x < end) {
+            self.interrupts.remove(pos);
+            return Err(io::Error::from_raw_os_error(libc::EINTR));
+        }
+        for &(ref range, errno) in &self.failures {
+            if range.contains(&start) {
+                return Err(io::Error::from_raw_os_error(errno));
+            }
+            if range.start > start && range.start < end {
+                end = range.start;
+            }
+        }
+
+        let len = (end - start) as usize;
+        let n = self.inner.read(&mut buf[..len])?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn size(&self) -> io::Result<u64> { self.inner.size() }
+
+    fn name(&self) -> &'static str { "faulty" }
+
+    fn skip(&mut self, bytes: u64) -> io::Result<()> {
+        self.inner.skip(bytes)?;
+        self.offset += bytes;
+        Ok(())
+    }
+
+    fn advise(&mut self, advice: Advice) -> Result<()> { self.inner.advise(advice) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fastfile::FastFileRead;
+
+    use spectral::prelude::*;
+
+    fn read_all(ffr: &mut FastFileReader) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        loop {
+            let buf = FastFileRead::read(ffr)?;
+            if buf.is_empty() {
+                return Ok(content);
+            }
+            content.extend_from_slice(buf);
+        }
+    }
+
+    #[test]
+    fn fixture_is_deterministic_and_deleted_on_drop() {
+        let fixture = fixture(10_000, 42).expect("Failed to create fixture");
+        let path = fixture.path().to_path_buf();
+
+        let content = std::fs::read(&path).expect("Failed to read fixture");
+        asserting("Content")
+            .that(&content)
+            .is_equal_to(fixture_bytes(10_000, 42));
+        asserting("Different seed")
+            .that(&(fixture_bytes(10_000, 43) != content))
+            .is_true();
+
+        drop(fixture);
+        asserting("Deleted").that(&path.exists()).is_false();
+    }
+
+    #[test]
+    fn memory_backend_reads_correctly() {
+        let expected = fixture_bytes(100_000, 1);
+        let mut ffr = reader(MemoryBackend::new(expected.clone())).expect("Failed to create reader");
+
+        let content = read_all(&mut ffr).expect("Failed to read");
+
+        asserting("Content").that(&content).is_equal_to(&expected);
+    }
+
+    #[test]
+    fn faulty_backend_interrupts_and_short_reads_are_handled() {
+        let expected = fixture_bytes(100_000, 2);
+        let backend = FaultyBackend::new(MemoryBackend::new(expected.clone()))
+            .short_reads(1000)
+            .interrupt_at(0)
+            .interrupt_at(50_000);
+        let mut ffr = reader(backend).expect("Failed to create reader");
+
+        let mut content = Vec::new();
+        io::Read::read_to_end(&mut ffr, &mut content).expect("Failed to read");
+
+        asserting("Content").that(&content).is_equal_to(&expected);
+    }
+
+    #[test]
+    fn faulty_backend_fails_at_offset() {
+        let backend = FaultyBackend::new(MemoryBackend::new(fixture_bytes(100_000, 3))).fail_at(70_000);
+        let mut ffr = reader(backend).expect("Failed to create reader");
+
+        let res = read_all(&mut ffr);
+
+        asserting("Read fails").that(&res.is_err()).is_true();
+        let error = Error::from(res.err().unwrap()); // Safe, bc we checked above
+        asserting("Error kind")
+            .that(error.kind())
+            .is_equal_to(&ErrorKind::ReadFailed {
+                offset:   70_000,
+                consumed: 70_000,
+            });
+        asserting("Errno")
+            .that(&error.raw_os_error())
+            .is_equal_to(Some(libc::EIO));
+    }
+
+    #[test]
+    fn faulty_backend_fail_range_is_zero_filled() {
+        let page_size = crate::os::PAGE_SIZE as u64;
+        let mut expected = fixture_bytes(100_000, 4);
+        let backend = FaultyBackend::new(MemoryBackend::new(expected.clone())).fail_range(page_size..2 * page_size + 1);
+        let mut ffr = reader_with_policy(backend, ReadErrorPolicy::ZeroFill).expect("Failed to create reader");
+
+        let content = read_all(&mut ffr).expect("Failed to read");
+
+        for x in expected[page_size as usize..3 * page_size as usize].iter_mut() {
+            *x = 0;
+        }
+        asserting("Content").that(&content).is_equal_to(&expected);
+        asserting("Failed ranges")
+            .that(&ffr.failed_ranges().to_vec())
+            .is_equal_to(vec![page_size..3 * page_size]);
+    }
+
+    #[test]
+    fn faulty_backend_truncates() {
+        let backend = FaultyBackend::new(MemoryBackend::new(fixture_bytes(100_000, 5))).truncate_at(12_345);
+        let mut ffr = reader(backend).expect("Failed to create reader");
+
+        let content = read_all(&mut ffr).expect("Failed to read");
+
+        asserting("Truncated").that(&content.len()).is_equal_to(12_345);
+        asserting("Size").that(&ffr.size()).is_equal_to(100_000);
+    }
+}
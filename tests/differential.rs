@@ -0,0 +1,309 @@
+//! Differential tests that compare every reader strategy and read API against `std::fs::read`
+//!
+//! Every API is checked for the size classes of the benchmarks up to 16 MiB and the edge cases
+//! around the page size and the read buffer limits. On top of that, properties check random file
+//! sizes and random sequences of mixed read calls; failing cases are shrunk to a minimal file size
+//! and call sequence. Set `QUICKCHECK_TESTS` to run more random cases.
+
+use fastfile::{
+    backend::Advice,
+    fastfile::{FastFileReader, FastFileReaderBuilder, MIN_READ_BUF_SIZE},
+    os::PAGE_SIZE,
+    prelude::*,
+    strategy::{CacheAwareReaderStrategy, DefaultReaderStrategy, Fallback, ReaderStrategy, SizeSwitch, WithAdvice},
+    testing::{self, FileStrategy, MmapStrategy},
+    FastFileRead,
+};
+use fastfile_benches::benches::{FILE_SIZES_MEDIUM, FILE_SIZES_SMALL, FILE_SIZES_VERY_SMALL};
+use quickcheck::{Arbitrary, Gen, QuickCheck, TestResult};
+use rand::Rng;
+use std::io::Read;
+
+const MAX_FILE_SIZE: usize = 16 * 1024 * 1024;
+const MAX_RANDOM_FILE_SIZE: usize = 2 * MAX_READ_BUF_SIZE + PAGE_SIZE;
+const RANDOM_CASES: u64 = 24;
+
+fn strategies() -> Vec<(&'static str, Box<dyn ReaderStrategy>)> {
+    #[allow(unused_mut)]
+    let mut strategies: Vec<(&'static str, Box<dyn ReaderStrategy>)> = vec![
+        ("file", Box::new(FileStrategy)),
+        ("mmap or file", Box::new(Fallback(MmapStrategy, FileStrategy))),
+        (
+            "size switch",
+            Box::new(SizeSwitch::new(Fallback(MmapStrategy, FileStrategy)).below(64 * 1024, FileStrategy)),
+        ),
+        (
+            "file with advice",
+            Box::new(Fallback(WithAdvice(FileStrategy, Advice::Sequential), FileStrategy)),
+        ),
+        ("default", Box::new(DefaultReaderStrategy::default())),
+        ("adaptive buffer", Box::new(AdaptiveBufferStrategy)),
+        ("cache aware", Box::new(CacheAwareReaderStrategy::default())),
+    ];
+    #[cfg(feature = "calibration")]
+    strategies.push(("profiled", Box::new(profiled())));
+
+    strategies
+}
+
+/// `AdaptiveBufferStrategy` starts with a buffer of one page and lets the reader grow it
+struct AdaptiveBufferStrategy;
+
+impl ReaderStrategy for AdaptiveBufferStrategy {
+    fn get_reader(&self, ffrb: FastFileReaderBuilder) -> fastfile::errors::Result<FastFileReader> {
+        let mut reader = FileStrategy.get_reader(ffrb.with_size_hint(PAGE_SIZE))?;
+        reader.set_buffer_sizes(PAGE_SIZE, 16 * PAGE_SIZE);
+        reader.set_adaptive_buffer(true);
+
+        Ok(reader)
+    }
+}
+
+#[cfg(feature = "calibration")]
+fn profiled() -> fastfile::strategy::ProfiledReaderStrategy {
+    use fastfile::strategy::{Profile, ProfileBackend, ProfiledReaderStrategy, SizeClass};
+
+    let profile = Profile {
+        size_classes: vec![
+            SizeClass {
+                max_size:    Some(64 * 1024),
+                backend:     ProfileBackend::File,
+                buffer_size: PAGE_SIZE,
+            },
+            SizeClass {
+                max_size:    None,
+                backend:     ProfileBackend::Mmap,
+                buffer_size: 16 * PAGE_SIZE,
+            },
+        ],
+    };
+
+    ProfiledReaderStrategy::new(profile).expect("Failed to create profiled strategy")
+}
+
+fn file_sizes() -> Vec<usize> {
+    let mut sizes = vec![
+        0,
+        1,
+        PAGE_SIZE - 1,
+        PAGE_SIZE,
+        PAGE_SIZE + 1,
+        2 * PAGE_SIZE,
+        MIN_READ_BUF_SIZE + 1,
+        MAX_READ_BUF_SIZE - 1,
+        MAX_READ_BUF_SIZE,
+        MAX_READ_BUF_SIZE + 1,
+        2 * MAX_READ_BUF_SIZE + PAGE_SIZE + 1,
+    ];
+    sizes.extend(
+        FILE_SIZES_VERY_SMALL
+            .iter()
+            .chain(FILE_SIZES_SMALL)
+            .chain(FILE_SIZES_MEDIUM)
+            .filter(|size| **size <= MAX_FILE_SIZE),
+    );
+    sizes.sort();
+    sizes.dedup();
+
+    sizes
+}
+
+/// Size of a random test file; biased towards multiples of the page size and their neighbours
+#[derive(Clone, Copy, Debug)]
+struct FileSize(usize);
+
+impl Arbitrary for FileSize {
+    fn arbitrary<G: Gen>(g: &mut G) -> FileSize {
+        let size = match g.gen_range(0, 3) {
+            0 => g.gen_range(0, 4 * PAGE_SIZE),
+            1 => (g.gen_range(0, 64) * PAGE_SIZE + g.gen_range(0, 3)).saturating_sub(1),
+            _ => g.gen_range(0, MAX_RANDOM_FILE_SIZE),
+        };
+
+        FileSize(size)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = FileSize>> { Box::new(self.0.shrink().map(FileSize)) }
+}
+
+/// Call of a read API; the calls of a sequence are repeated until EOF
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ReadCall {
+    Read,
+    ReadFull,
+    ReadToEnd,
+    /// `io::Read::read` into a buffer of the given non-zero length
+    IoRead(usize),
+}
+
+impl Arbitrary for ReadCall {
+    fn arbitrary<G: Gen>(g: &mut G) -> ReadCall {
+        match g.gen_range(0, 4) {
+            0 => ReadCall::Read,
+            1 => ReadCall::ReadFull,
+            2 => ReadCall::ReadToEnd,
+            _ => ReadCall::IoRead(g.gen_range(1, 3 * PAGE_SIZE)),
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = ReadCall>> {
+        match *self {
+            ReadCall::IoRead(len) => Box::new(len.shrink().filter(|len| *len > 0).map(ReadCall::IoRead)),
+            _ => quickcheck::empty_shrinker(),
+        }
+    }
+}
+
+/// Reads the file with `calls` and returns the content and the lengths of the chunks
+fn read_with(ffr: &mut FastFileReader, calls: &[ReadCall]) -> std::io::Result<(Vec<u8>, Vec<usize>)> {
+    let mut content = Vec::new();
+    let mut chunks = Vec::new();
+    let mut buf = vec![0u8; 3 * PAGE_SIZE];
+    for call in calls.iter().cycle() {
+        let len = match *call {
+            ReadCall::Read => {
+                let chunk = FastFileRead::read(ffr)?;
+                content.extend_from_slice(chunk);
+                chunk.len()
+            }
+            ReadCall::ReadFull => {
+                let chunk = ffr.read_full()?;
+                content.extend_from_slice(chunk);
+                chunk.len()
+            }
+            ReadCall::ReadToEnd => {
+                content.extend_from_slice(FastFileRead::read_to_end(ffr)?);
+                if !FastFileRead::read(ffr)?.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "read after read_to_end returned data",
+                    ));
+                }
+                0
+            }
+            ReadCall::IoRead(len) => {
+                let n = Read::read(ffr, &mut buf[..len])?;
+                content.extend_from_slice(&buf[..n]);
+                n
+            }
+        };
+        if len == 0 {
+            break;
+        }
+        chunks.push(len);
+    }
+
+    Ok((content, chunks))
+}
+
+/// Reads a file of `size` bytes with `calls` and every strategy and compares the result to
+/// `std::fs::read`; returns a description of the first difference
+fn differs(size: usize, calls: &[ReadCall]) -> Option<String> {
+    let fixture = testing::fixture(size, size as u64).expect("Failed to create test file");
+    let expected = std::fs::read(fixture.path()).expect("Failed to read test file with std");
+
+    for (name, strategy) in strategies() {
+        let context = format!("strategy={}, size={}, calls={:?}", name, size, calls);
+        let mut ffr = match FastFile::read(fixture.path())
+            .expect("Failed to create FastFileReaderBuilder")
+            .open_with_strategy(strategy.as_ref())
+        {
+            Ok(ffr) => ffr,
+            Err(e) => return Some(format!("Failed to open test file: {}; {}", e, context)),
+        };
+
+        let (actual, chunks) = match read_with(&mut ffr, calls) {
+            Ok(read) => read,
+            Err(e) => return Some(format!("Failed to read: {}; {}", e, context)),
+        };
+
+        if let Some(pos) = expected.iter().zip(&actual).position(|(e, a)| e != a) {
+            return Some(format!("Content differs at byte {}; {}", pos, context));
+        }
+        if expected.len() != actual.len() {
+            return Some(format!(
+                "Lengths differ: {} != {}; {}",
+                expected.len(),
+                actual.len(),
+                context
+            ));
+        }
+        // All chunks of `read_full` but the last one are full
+        if calls.iter().all(|call| *call == ReadCall::ReadFull) && chunks.len() > 1 {
+            let full = chunks[0];
+            if chunks[..chunks.len() - 1].iter().any(|len| *len != full) {
+                return Some(format!("Chunks are not full: {:?}; {}", chunks, context));
+            }
+        }
+    }
+
+    None
+}
+
+fn check(size: usize, calls: &[ReadCall]) -> TestResult {
+    match differs(size, calls) {
+        Some(difference) => TestResult::error(difference),
+        None => TestResult::passed(),
+    }
+}
+
+/// Checks `calls` for the edge case sizes and `property` for random sizes
+fn differential(calls: &[ReadCall], property: fn(FileSize) -> TestResult) {
+    for size in file_sizes() {
+        if let Some(difference) = differs(size, calls) {
+            panic!("{}", difference);
+        }
+    }
+    QuickCheck::new().tests(RANDOM_CASES).quickcheck(property);
+}
+
+#[test]
+fn fastfileread_read() {
+    fn property(size: FileSize) -> TestResult { check(size.0, &[ReadCall::Read]) }
+    differential(&[ReadCall::Read], property);
+}
+
+#[test]
+fn fastfileread_read_full() {
+    fn property(size: FileSize) -> TestResult { check(size.0, &[ReadCall::ReadFull]) }
+    differential(&[ReadCall::ReadFull], property);
+}
+
+#[test]
+fn fastfileread_read_to_end() {
+    fn property(size: FileSize) -> TestResult { check(size.0, &[ReadCall::ReadToEnd]) }
+    differential(&[ReadCall::ReadToEnd], property);
+}
+
+#[test]
+fn io_read() {
+    fn property(size: FileSize) -> TestResult { check(size.0, &[ReadCall::IoRead(8 * 1024)]) }
+    differential(&[ReadCall::IoRead(8 * 1024)], property);
+}
+
+#[test]
+fn reads_then_full_read() {
+    // Enough reads for an adaptive buffer to grow before the full read
+    const CALLS: &[ReadCall] = &[
+        ReadCall::Read,
+        ReadCall::Read,
+        ReadCall::Read,
+        ReadCall::Read,
+        ReadCall::ReadFull,
+    ];
+    fn property(size: FileSize) -> TestResult { check(size.0, CALLS) }
+    differential(CALLS, property);
+}
+
+#[test]
+fn mixed_reads() {
+    fn property(size: FileSize, calls: Vec<ReadCall>) -> TestResult {
+        if calls.is_empty() {
+            return TestResult::discard();
+        }
+        check(size.0, &calls)
+    }
+    QuickCheck::new()
+        .tests(RANDOM_CASES)
+        .quickcheck(property as fn(FileSize, Vec<ReadCall>) -> TestResult);
+}